@@ -0,0 +1,45 @@
+use std::time::Instant;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use backprop::diagnostics::graph_size;
+use backprop::nn::{inputs, softmax_cross_entropy, Activation, MLP};
+use backprop::optim::{accumulate_gradients, Gradients};
+
+fn main() {
+    // cargo run --release --bin checkpoint
+    // every neuron holds a copy of the graph of all its inputs, so the graph of a deep MLP
+    // grows exponentially with the depth even when it is narrow
+    let layer_sizes = [4, 5, 5, 5, 5, 5, 5, 3];
+    let mlp = MLP::new(&layer_sizes, Activation::Tanh, &mut StdRng::seed_from_u64(42));
+    let x = inputs(&[0.5, -1.2, 0.3, 2.0]);
+    println!("MLP {:?}", layer_sizes);
+    println!("{:<22} {:>32} {:>10} {:>16}", "layers per checkpoint", "graph kept after forward", "time", "max |grad diff|");
+
+    let mut reference: Option<Gradients> = None;
+    for layers_per_segment in [0, 3, 2, 1] {
+        let start = Instant::now();
+        let outputs = if layers_per_segment == 0 {
+            mlp.forward(&x)
+        } else {
+            mlp.forward_checkpointed(&x, layers_per_segment)
+        };
+        let mut loss = softmax_cross_entropy(&outputs, 0);
+        let size = graph_size(&loss);
+        loss.backward();
+        let elapsed = start.elapsed();
+
+        let mut grads = Gradients::new();
+        accumulate_gradients(&loss, &mut grads);
+        let max_diff = match &reference {
+            Some(expected) => mlp.parameters().iter()
+                .map(|p| (expected[&p.id()] - grads[&p.id()]).abs())
+                .fold(0., f64::max),
+            None => 0.,
+        };
+        let name = if layers_per_segment == 0 { "none".to_string() } else { layers_per_segment.to_string() };
+        println!("{:<22} {:>32} {:>8.0}ms {:>16.2e}", name, size.to_string(), elapsed.as_secs_f64() * 1e3, max_diff);
+        reference.get_or_insert(grads);
+    }
+}
@@ -0,0 +1,65 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use backprop::conv::{self, Conv2d, Pooling};
+use backprop::datasets::{self, DIGIT_SIZE};
+use backprop::nn::{Activation, Layer, Model};
+use backprop::optim::Adam;
+use backprop::plot::draw_loss_curve;
+use backprop::train::{accuracy, fit, BatchMode, TrainConfig};
+use backprop::Value;
+
+/// conv 3x3 -> relu -> max pool 2x2 -> dense layer producing the logits of the 10 digits
+struct DigitsNet {
+    conv: Conv2d,
+    dense: Layer,
+}
+
+impl DigitsNet {
+    fn new(filters: usize, rng: &mut impl Rng) -> DigitsNet {
+        // 8x8 image -> 6x6 feature maps -> 3x3 after pooling
+        let pooled = (DIGIT_SIZE - 2) / 2;
+        DigitsNet {
+            conv: Conv2d::new(1, filters, 3, 1, "0", rng),
+            dense: Layer::new(filters * pooled * pooled, 10, Activation::Linear, 1, rng),
+        }
+    }
+}
+
+impl Model for DigitsNet {
+    fn forward_sample(&self, features: &[f64]) -> Vec<Value> {
+        let image = conv::image(features, 1, DIGIT_SIZE, DIGIT_SIZE);
+        let maps = conv::map(&self.conv.forward(&image), Value::relu);
+        let pooled = conv::pool2d(&maps, 2, Pooling::Max);
+        self.dense.forward(&conv::flatten(&pooled))
+    }
+
+    fn parameters(&self) -> Vec<&Value> {
+        self.conv.parameters().into_iter().chain(self.dense.parameters()).collect()
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut Value> {
+        self.conv.parameters_mut().into_iter().chain(self.dense.parameters_mut()).collect()
+    }
+}
+
+fn main() {
+    // cargo run --release --bin digits
+    let digits = datasets::synthetic_digits(30, 0.2, 42);
+    let (train, test) = digits.split_with_ratio(0.8, 42);
+    println!("Training on digits with #{} samples", train.len());
+
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut model = DigitsNet::new(4, &mut rng);
+    let mut optimizer = Adam::new(0.02);
+    let config = TrainConfig {
+        epochs: 30,
+        batch_mode: BatchMode::ParallelDeterministic,
+        log_every: 5,
+        ..TrainConfig::default()
+    };
+
+    let history = fit(&mut model, &train, &mut optimizer, &config);
+    println!("digits test accuracy {:.3}", accuracy(&model, &test));
+    let _ = draw_loss_curve(&history.losses, "loss_digits.jpg", "Loss on synthetic digits");
+}
@@ -0,0 +1,54 @@
+use std::env;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use backprop::datasets::{self, Sequence};
+use backprop::optim::{Adam, GradientClip};
+use backprop::plot::draw_loss_curve;
+use backprop::recurrent::{sequence_accuracy, GRUCell, LSTMCell, RNNCell, RecurrentCell, SequenceClassifier, SequenceSet};
+use backprop::train::{fit, BatchMode, TrainConfig};
+
+const HIDDEN_SIZE: usize = 8;
+// the whole training strings, the parity needs the state of every previous step
+const TRUNCATION: usize = 8;
+
+/// Trains `cell` on the running parity of bit strings and saves the loss curve
+fn run<C: RecurrentCell>(name: &str, cell: C, train: &[Sequence], test: &[Sequence], config: &TrainConfig) {
+    println!("Training a {} on #{} sequences", name, train.len());
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let mut model = SequenceClassifier::new(cell, 2, &mut rng);
+    let mut optimizer = Adam::new(0.05);
+
+    let history = fit(&mut model, &SequenceSet { sequences: train, truncation: TRUNCATION }, &mut optimizer, config);
+    println!("{} test accuracy {:.3}", name, sequence_accuracy(&model, test));
+    let _ = draw_loss_curve(&history.losses, &format!("loss_parity_{}.jpg", name), &format!("Parity loss of the {}", name));
+}
+
+fn main() {
+    // cargo run --release --bin sequences -- [rnn|gru|lstm]
+    let selected: Vec<String> = env::args().skip(1).collect();
+    let wanted = |name: &str| selected.is_empty() || selected.iter().any(|s| s == name);
+    // trained on strings of 8 bits, tested on longer ones to check that the state carries the parity
+    let train = datasets::parity_sequences(100, 8, 42);
+    let test = datasets::parity_sequences(50, 16, 7);
+    let config = TrainConfig {
+        epochs: 30,
+        batch_size: 10,
+        log_every: 5,
+        batch_mode: BatchMode::ParallelDeterministic,
+        clip: Some(GradientClip::Norm(1.)),
+        ..TrainConfig::default()
+    };
+
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    if wanted("rnn") {
+        run("rnn", RNNCell::new(1, HIDDEN_SIZE, &mut rng), &train, &test, &config);
+    }
+    if wanted("gru") {
+        run("gru", GRUCell::new(1, HIDDEN_SIZE, &mut rng), &train, &test, &config);
+    }
+    if wanted("lstm") {
+        run("lstm", LSTMCell::new(1, HIDDEN_SIZE, &mut rng), &train, &test, &config);
+    }
+}
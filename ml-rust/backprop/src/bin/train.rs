@@ -0,0 +1,86 @@
+use std::env;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use backprop::datasets::{self, Samples};
+use backprop::export::{save_onnx, save_rust_module};
+use backprop::nn::{argmax, Activation, MLP};
+use backprop::optim::{Adam, GradientClip};
+use backprop::regularization::Penalty;
+use backprop::schedule::LrSchedule;
+use backprop::plot::{draw_decision_boundary, draw_loss_curve};
+use backprop::serialize::{save_params, Format};
+use backprop::train::{accuracy, fit, BatchMode, TrainConfig};
+
+/// Trains an MLP on `train`, evaluates it on `test` and saves the loss curve,
+/// the decision boundary in the plane of the features `axes` and the parameters
+fn run(name: &str, train: &Samples, test: &Samples, hidden: &[usize], axes: (usize, usize), config: &TrainConfig) {
+    println!("Training on {} with #{} samples", name, train.len());
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let layer_sizes: Vec<usize> = std::iter::once(train.n_features())
+        .chain(hidden.iter().copied())
+        .chain(std::iter::once(train.n_classes))
+        .collect();
+    let mut model = MLP::new(&layer_sizes, Activation::Tanh, &mut rng);
+    let mut optimizer = Adam::new(0.01);
+
+    let history = fit(&mut model, train, &mut optimizer, config);
+    println!("{} test accuracy {:.3}", name, accuracy(&model, test));
+
+    let _ = draw_loss_curve(&history.losses, &format!("loss_{}.jpg", name), &format!("Loss on {}", name));
+    // the features not drawn are fixed at zero, i.e. at their mean for standardized data
+    let n_features = train.n_features();
+    let _ = draw_decision_boundary(train, axes, |x, y| {
+        let mut features = vec![0.; n_features];
+        features[axes.0] = x;
+        features[axes.1] = y;
+        argmax(&model.predict(&features))
+    }, &format!("decision_boundary_{}.jpg", name));
+    let _ = save_params(&model.parameter_set(), &format!("mlp_{}.json", name), Format::Json);
+    // standalone copies of the network for inference without the autograd engine
+    if let Err(e) = save_onnx(&model, &format!("mlp_{}.onnx", name)) {
+        println!("ONNX export of {} failed: {}", name, e);
+    }
+    let _ = save_rust_module(&model, &format!("mlp_{}.rs", name));
+}
+
+fn main() {
+    // cargo run --release --bin train -- [iris|moons|circles]
+    // the samples of a batch are processed on RAYON_NUM_THREADS threads, by default one per core
+    let selected: Vec<String> = env::args().skip(1).collect();
+    let wanted = |name: &str| selected.is_empty() || selected.iter().any(|s| s == name);
+    let base_config = TrainConfig {
+        batch_mode: BatchMode::ParallelDeterministic,
+        clip: Some(GradientClip::Norm(5.)),
+        ..TrainConfig::default()
+    };
+    let warmup_cosine = LrSchedule::Warmup {
+        epochs: 10,
+        then: Box::new(LrSchedule::CosineAnnealing { t_max: 140, min_lr: 1e-4 }),
+    };
+
+    if wanted("iris") {
+        let (mut train, mut test) = datasets::iris().split_with_ratio(0.9, 42);
+        // with the statistics of the training set only, so that nothing of the test set leaks into training
+        train.standardize().apply(&mut test);
+        let config = TrainConfig {
+            epochs: 100,
+            schedule: LrSchedule::Step { step_size: 40, gamma: 0.5 },
+            penalty: Some(Penalty::L2(1e-4)),
+            ..base_config.clone()
+        };
+        // petal length and width separate the species best
+        run("iris", &train, &test, &[8], (2, 3), &config);
+    }
+    if wanted("moons") {
+        let (train, test) = datasets::moons(300, 0.1, 7).split_with_ratio(0.8, 42);
+        let config = TrainConfig { epochs: 150, schedule: warmup_cosine.clone(), ..base_config.clone() };
+        run("moons", &train, &test, &[8, 8], (0, 1), &config);
+    }
+    if wanted("circles") {
+        let (train, test) = datasets::circles(300, 0.05, 0.5, 7).split_with_ratio(0.8, 42);
+        let config = TrainConfig { epochs: 150, schedule: warmup_cosine.clone(), ..base_config.clone() };
+        run("circles", &train, &test, &[8, 8], (0, 1), &config);
+    }
+}
@@ -0,0 +1,139 @@
+use std::fmt;
+use std::sync::Arc;
+
+use super::{next_id, Op, Value};
+
+/// Function rebuilding the graph of a checkpointed segment from its inputs
+pub type SegmentFn = dyn Fn(&[Value]) -> Value + Send + Sync;
+
+/// Recompute function of a `Checkpoint` node, it is not serialized so a loaded checkpoint
+/// keeps its data but can't back-propagate
+#[derive(Clone)]
+pub struct Recompute {
+    f: Arc<SegmentFn>,
+    // the node's first `n_inputs` children are the inputs of the segment, the other ones
+    // are the leaves found inside it during backward, e.g. parameters captured by `f`
+    n_inputs: usize,
+}
+
+impl fmt::Debug for Recompute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Recompute {{ n_inputs: {} }}", self.n_inputs)
+    }
+}
+
+// leaf standing for an input in the segment graph, it keeps the id to find its gradient
+fn input_leaf(input: &Value) -> Value {
+    let mut leaf = Value::leaf(&input.label, input.data);
+    leaf.set_id(input._id);
+    leaf
+}
+
+fn collect_leaves(value: Value, leaves: &mut Vec<Value>) {
+    if value.children.is_empty() {
+        leaves.push(value);
+    } else {
+        for child in value.children {
+            collect_leaves(child, leaves);
+        }
+    }
+}
+
+impl Value {
+    /// Runs `f` on `inputs` but keeps only its result: the intermediate nodes of the segment
+    /// are dropped after the forward pass and rebuilt by running `f` again during backward.
+    /// Parameters used by `f` can be captured by the closure, they are added as children of
+    /// the checkpoint during backward so that `accumulate_gradients` still finds them
+    pub fn checkpoint(inputs: Vec<Value>, f: impl Fn(&[Value]) -> Value + Send + Sync + 'static) -> Value {
+        let leaves: Vec<Value> = inputs.iter().map(input_leaf).collect();
+        let data = f(&leaves).data;
+        let mut out = Value::default();
+        out.set_id(next_id());
+        out.set_backward(Some(Self::backward_checkpoint));
+        out.set_data(data);
+        out.set_op(Op::Checkpoint);
+        out.recompute = Some(Recompute { f: Arc::new(f), n_inputs: inputs.len() });
+        out.set_children(inputs);
+
+        out
+    }
+
+    /// Checkpoints each output of a segment with several outputs, e.g. a block of layers.
+    /// Every output keeps its own copy of the inputs and rebuilds the whole segment in backward,
+    /// so the memory saved grows with the size of the segment while the compute grows with
+    /// the number of outputs
+    pub fn checkpoint_many(
+        inputs: &[Value],
+        n_outputs: usize,
+        f: impl Fn(&[Value]) -> Vec<Value> + Send + Sync + 'static,
+    ) -> Vec<Value> {
+        let f = Arc::new(f);
+        (0..n_outputs)
+            .map(|j| {
+                let f = Arc::clone(&f);
+                Value::checkpoint(inputs.to_vec(), move |x| f(x).swap_remove(j))
+            })
+            .collect()
+    }
+
+    /// Graph the checkpoint stands for, with the segment rebuilt on top of the inputs.
+    /// `None` for other nodes and for checkpoints loaded from a file
+    pub fn expand_checkpoint(&self) -> Option<Value> {
+        let recompute = self.recompute.as_ref()?;
+        Some((recompute.f)(&self.children[..recompute.n_inputs]))
+    }
+
+    pub(crate) fn backward_checkpoint(v: &mut Value) {
+        let Some(Recompute { f, n_inputs }) = v.recompute.clone() else {
+            return;
+        };
+        // the segment is rebuilt on leaves, the inputs' own graphs are back-propagated by the caller
+        let leaves: Vec<Value> = v.children[..n_inputs].iter().map(input_leaf).collect();
+        let mut segment = f(&leaves);
+        // leaves added by an earlier backward would be counted twice
+        v.children.truncate(n_inputs);
+        segment.backward();
+        let mut leaves = Vec::new();
+        collect_leaves(segment, &mut leaves);
+        for mut leaf in leaves {
+            leaf.grad *= v.grad;
+            match v.children[..n_inputs].iter_mut().find(|c| c._id == leaf._id) {
+                Some(input) => input.grad += leaf.grad,
+                None if !leaf.is_constant() => v.children.push(leaf),
+                None => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::nn::{Activation, MLP};
+    use crate::optim::{accumulate_gradients, Gradients};
+
+    fn gradients(mut loss: Value) -> Gradients {
+        loss.backward();
+        let mut grads = Gradients::new();
+        accumulate_gradients(&loss, &mut grads);
+        grads
+    }
+
+    #[test]
+    fn checkpointed_gradients_match() {
+        let mlp = MLP::new(&[3, 4, 4, 4, 2], Activation::Tanh, &mut StdRng::seed_from_u64(0));
+        let x = crate::nn::inputs(&[0.5, -1.0, 2.0]);
+        let plain = Value::sum(mlp.forward(&x));
+        let checkpointed = Value::sum(mlp.forward_checkpointed(&x, 2));
+        assert!((plain.data() - checkpointed.data()).abs() < 1e-12);
+
+        let (expected, grads) = (gradients(plain), gradients(checkpointed));
+        for p in mlp.parameters().into_iter().chain(x.iter()) {
+            let (want, got) = (expected[&p.id()], grads[&p.id()]);
+            assert!((want - got).abs() < 1e-12, "{}: {} != {}", p.label(), got, want);
+        }
+    }
+}
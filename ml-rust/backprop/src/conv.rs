@@ -0,0 +1,243 @@
+use ndarray::prelude::*;
+use ndarray::Dimension;
+use rand::Rng;
+
+use super::Value;
+
+/// Leaves holding an image, `pixels` is in row major order and has `channels * height * width` values
+pub fn image(pixels: &[f64], channels: usize, height: usize, width: usize) -> Array3<Value> {
+    Array3::from_shape_vec((channels, height, width), super::nn::inputs(pixels))
+        .expect("pixels match the image shape")
+}
+
+/// 1-D convolution without padding. Input is `(channels, length)`,
+/// output is `(out_channels, (length - kernel_size) / stride + 1)`
+#[derive(Debug, Clone)]
+pub struct Conv1d {
+    // (out_channels, in_channels, kernel_size)
+    weights: Array3<Value>,
+    bias: Array1<Value>,
+    stride: usize,
+}
+
+impl Conv1d {
+    pub fn new(in_channels: usize, out_channels: usize, kernel_size: usize, stride: usize, name: &str, rng: &mut impl Rng) -> Conv1d {
+        assert!(kernel_size > 0 && stride > 0, "kernel size and stride must be positive");
+        let bound = 1. / ((in_channels * kernel_size) as f64).sqrt();
+        Conv1d {
+            weights: Array3::from_shape_fn((out_channels, in_channels, kernel_size), |(o, c, k)| {
+                Value::leaf(&format!("k{}_{}_{}_{}", name, o, c, k), rng.random_range(-bound..bound))
+            }),
+            bias: Array1::from_shape_fn(out_channels, |o| Value::leaf(&format!("b{}_{}", name, o), 0.)),
+            stride,
+        }
+    }
+
+    pub fn forward(&self, x: &Array2<Value>) -> Array2<Value> {
+        let (out_channels, in_channels, kernel_size) = self.weights.dim();
+        assert_eq!(x.dim().0, in_channels, "input channels");
+        assert!(x.dim().1 >= kernel_size, "input of length {} is shorter than the kernel ({})", x.dim().1, kernel_size);
+        let out_len = (x.dim().1 - kernel_size) / self.stride + 1;
+        Array2::from_shape_fn((out_channels, out_len), |(o, t)| {
+            let start = t * self.stride;
+            let products = (0..in_channels)
+                .flat_map(|c| (0..kernel_size).map(move |k| (c, k)))
+                .map(|(c, k)| self.weights[[o, c, k]].clone() * x[[c, start + k]].clone());
+            self.bias[o].clone() + Value::sum(products)
+        })
+    }
+
+    pub fn parameters(&self) -> Vec<&Value> {
+        self.weights.iter().chain(self.bias.iter()).collect()
+    }
+
+    pub fn parameters_mut(&mut self) -> Vec<&mut Value> {
+        self.weights.iter_mut().chain(self.bias.iter_mut()).collect()
+    }
+}
+
+/// 2-D convolution without padding. Input is `(channels, height, width)`,
+/// output is `(out_channels, (height - kernel) / stride + 1, (width - kernel) / stride + 1)`
+#[derive(Debug, Clone)]
+pub struct Conv2d {
+    // (out_channels, in_channels, kernel_size, kernel_size)
+    weights: Array4<Value>,
+    bias: Array1<Value>,
+    stride: usize,
+}
+
+impl Conv2d {
+    pub fn new(in_channels: usize, out_channels: usize, kernel_size: usize, stride: usize, name: &str, rng: &mut impl Rng) -> Conv2d {
+        assert!(kernel_size > 0 && stride > 0, "kernel size and stride must be positive");
+        let bound = 1. / ((in_channels * kernel_size * kernel_size) as f64).sqrt();
+        Conv2d {
+            weights: Array4::from_shape_fn((out_channels, in_channels, kernel_size, kernel_size), |(o, c, i, j)| {
+                Value::leaf(&format!("k{}_{}_{}_{}_{}", name, o, c, i, j), rng.random_range(-bound..bound))
+            }),
+            bias: Array1::from_shape_fn(out_channels, |o| Value::leaf(&format!("b{}_{}", name, o), 0.)),
+            stride,
+        }
+    }
+
+    pub fn forward(&self, x: &Array3<Value>) -> Array3<Value> {
+        let (out_channels, in_channels, kernel_size, _) = self.weights.dim();
+        let (channels, height, width) = x.dim();
+        assert_eq!(channels, in_channels, "input channels");
+        assert!(height >= kernel_size && width >= kernel_size,
+            "input of {}x{} is smaller than the kernel ({}x{})", height, width, kernel_size, kernel_size);
+        let out_height = (height - kernel_size) / self.stride + 1;
+        let out_width = (width - kernel_size) / self.stride + 1;
+        Array3::from_shape_fn((out_channels, out_height, out_width), |(o, r, c)| {
+            let (top, left) = (r * self.stride, c * self.stride);
+            let products = (0..in_channels)
+                .flat_map(|ch| (0..kernel_size).flat_map(move |i| (0..kernel_size).map(move |j| (ch, i, j))))
+                .map(|(ch, i, j)| self.weights[[o, ch, i, j]].clone() * x[[ch, top + i, left + j]].clone());
+            self.bias[o].clone() + Value::sum(products)
+        })
+    }
+
+    pub fn parameters(&self) -> Vec<&Value> {
+        self.weights.iter().chain(self.bias.iter()).collect()
+    }
+
+    pub fn parameters_mut(&mut self) -> Vec<&mut Value> {
+        self.weights.iter_mut().chain(self.bias.iter_mut()).collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pooling {
+    Max,
+    Avg,
+}
+
+fn pool(window: Vec<Value>, pooling: Pooling) -> Value {
+    match pooling {
+        Pooling::Max => Value::max(window),
+        Pooling::Avg => {
+            let n = window.len() as f64;
+            Value::sum(window) / n
+        }
+    }
+}
+
+/// Pooling over non overlapping windows of `size` along the last axis of `(channels, length)`,
+/// a remainder shorter than `size` is dropped
+pub fn pool1d(x: &Array2<Value>, size: usize, pooling: Pooling) -> Array2<Value> {
+    let (channels, length) = x.dim();
+    assert!(size > 0, "pool size must be positive");
+    assert!(length >= size, "input of length {} is shorter than the pool size ({})", length, size);
+    Array2::from_shape_fn((channels, length / size), |(c, t)| {
+        pool((0..size).map(|k| x[[c, t * size + k]].clone()).collect(), pooling)
+    })
+}
+
+/// Pooling over non overlapping `size x size` windows of each channel of `(channels, height, width)`
+pub fn pool2d(x: &Array3<Value>, size: usize, pooling: Pooling) -> Array3<Value> {
+    let (channels, height, width) = x.dim();
+    assert!(size > 0, "pool size must be positive");
+    assert!(height >= size && width >= size, "input of {}x{} is smaller than the pool size ({})", height, width, size);
+    Array3::from_shape_fn((channels, height / size, width / size), |(c, r, col)| {
+        let window = (0..size)
+            .flat_map(|i| (0..size).map(move |j| (i, j)))
+            .map(|(i, j)| x[[c, r * size + i, col * size + j]].clone())
+            .collect();
+        pool(window, pooling)
+    })
+}
+
+/// Applies `f`, e.g. `Value::relu`, to every element
+pub fn map<D: Dimension>(x: &Array<Value, D>, f: impl Fn(Value) -> Value) -> Array<Value, D> {
+    x.map(|v| f(v.clone()))
+}
+
+/// Elements in row major order, to feed a dense layer
+pub fn flatten<D: Dimension>(x: &Array<Value, D>) -> Vec<Value> {
+    x.iter().cloned().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::check_gradients;
+    use crate::test_util::{point, weighted_sum};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn conv1d_gradients() {
+        let conv = Conv1d::new(2, 3, 3, 2, "0", &mut StdRng::seed_from_u64(0));
+        let check = check_gradients(|x| {
+            let signal = Array2::from_shape_vec((2, 7), x.to_vec()).unwrap();
+            weighted_sum(&flatten(&conv.forward(&signal)))
+        }, &point(14), 1e-5);
+        assert!(check.passed(1e-6), "{:?}", check);
+    }
+
+    #[test]
+    fn conv2d_gradients() {
+        let conv = Conv2d::new(2, 2, 3, 1, "0", &mut StdRng::seed_from_u64(0));
+        let check = check_gradients(|x| {
+            let image = Array3::from_shape_vec((2, 4, 5), x.to_vec()).unwrap();
+            weighted_sum(&flatten(&map(&conv.forward(&image), Value::tanh)))
+        }, &point(40), 1e-5);
+        assert!(check.passed(1e-6), "{:?}", check);
+    }
+
+    #[test]
+    fn pooling_gradients() {
+        for pooling in [Pooling::Max, Pooling::Avg] {
+            let check = check_gradients(|x| {
+                let signal = Array2::from_shape_vec((2, 6), x[..12].to_vec()).unwrap();
+                let image = Array3::from_shape_vec((1, 4, 4), x[12..].to_vec()).unwrap();
+                let mut outputs = flatten(&pool1d(&signal, 3, pooling));
+                outputs.extend(flatten(&pool2d(&image, 2, pooling)));
+                weighted_sum(&outputs)
+            }, &point(28), 1e-5);
+            assert!(check.passed(1e-6), "{:?} {:?}", pooling, check);
+        }
+    }
+
+    #[test]
+    fn output_shapes_at_the_edges() {
+        let mut rng = StdRng::seed_from_u64(0);
+        // input as long as the kernel gives a single output
+        let conv = Conv1d::new(1, 2, 3, 2, "0", &mut rng);
+        assert_eq!(conv.forward(&Array2::from_shape_vec((1, 3), crate::nn::inputs(&[1., 2., 3.])).unwrap()).dim(), (2, 1));
+        let conv = Conv2d::new(1, 1, 2, 3, "0", &mut rng);
+        assert_eq!(conv.forward(&image(&point(10), 1, 2, 5)).dim(), (1, 1, 2));
+        assert_eq!(pool2d(&image(&point(10), 1, 2, 5), 2, Pooling::Max).dim(), (1, 1, 2));
+    }
+
+    #[test]
+    #[should_panic(expected = "input of length 2 is shorter than the kernel (3)")]
+    fn conv1d_input_shorter_than_kernel() {
+        let conv = Conv1d::new(1, 1, 3, 1, "0", &mut StdRng::seed_from_u64(0));
+        conv.forward(&Array2::from_shape_vec((1, 2), crate::nn::inputs(&[1., 2.])).unwrap());
+    }
+
+    #[test]
+    #[should_panic(expected = "input of 4x2 is smaller than the kernel (3x3)")]
+    fn conv2d_input_smaller_than_kernel() {
+        let conv = Conv2d::new(1, 1, 3, 1, "0", &mut StdRng::seed_from_u64(0));
+        conv.forward(&image(&point(8), 1, 4, 2));
+    }
+
+    #[test]
+    #[should_panic(expected = "kernel size and stride must be positive")]
+    fn zero_stride() {
+        Conv1d::new(1, 1, 3, 0, "0", &mut StdRng::seed_from_u64(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "pool size must be positive")]
+    fn zero_pool_size() {
+        pool1d(&Array2::from_shape_vec((1, 2), crate::nn::inputs(&[1., 2.])).unwrap(), 0, Pooling::Avg);
+    }
+
+    #[test]
+    #[should_panic(expected = "input of 1x4 is smaller than the pool size (2)")]
+    fn pool_larger_than_input() {
+        pool2d(&image(&point(4), 1, 1, 4), 2, Pooling::Max);
+    }
+}
@@ -0,0 +1,233 @@
+use std::f64::consts::PI;
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+/// Samples for classification with the `Value` based networks, one feature vector per sample
+#[derive(Debug, Clone, Default)]
+pub struct Samples {
+    pub features: Vec<Vec<f64>>,
+    pub targets: Vec<usize>,
+    pub feature_names: Vec<String>,
+    pub n_classes: usize,
+}
+
+impl Samples {
+    pub fn len(&self) -> usize {
+        self.targets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.targets.is_empty()
+    }
+
+    pub fn n_features(&self) -> usize {
+        self.feature_names.len()
+    }
+
+    /// Rescales every feature to zero mean and unit variance, returns the statistics used so that
+    /// the test set can be rescaled with those of the training set
+    pub fn standardize(&mut self) -> Standardization {
+        let n = self.len() as f64;
+        let mean: Vec<f64> = (0..self.n_features())
+            .map(|j| self.features.iter().map(|x| x[j]).sum::<f64>() / n)
+            .collect();
+        let std = (0..self.n_features())
+            .map(|j| {
+                let var = self.features.iter().map(|x| (x[j] - mean[j]).powi(2)).sum::<f64>() / n;
+                var.sqrt().max(1e-12)
+            })
+            .collect();
+        let standardization = Standardization { mean, std };
+        standardization.apply(self);
+        standardization
+    }
+
+    /// Shuffles the samples and splits them, `ratio` of them go to the first set
+    pub fn split_with_ratio(&self, ratio: f32, seed: u64) -> (Samples, Samples) {
+        let mut indices: Vec<usize> = (0..self.len()).collect();
+        indices.shuffle(&mut StdRng::seed_from_u64(seed));
+        let n_first = (self.len() as f32 * ratio).round() as usize;
+        let subset = |idx: &[usize]| Samples {
+            features: idx.iter().map(|&i| self.features[i].clone()).collect(),
+            targets: idx.iter().map(|&i| self.targets[i]).collect(),
+            feature_names: self.feature_names.clone(),
+            n_classes: self.n_classes,
+        };
+        (subset(&indices[..n_first]), subset(&indices[n_first..]))
+    }
+}
+
+/// Mean and standard deviation of every feature of the set `Samples::standardize` was called on
+#[derive(Debug, Clone, PartialEq)]
+pub struct Standardization {
+    pub mean: Vec<f64>,
+    pub std: Vec<f64>,
+}
+
+impl Standardization {
+    pub fn apply(&self, samples: &mut Samples) {
+        for x in samples.features.iter_mut() {
+            for (j, value) in x.iter_mut().enumerate() {
+                *value = (*value - self.mean[j]) / self.std[j];
+            }
+        }
+    }
+}
+
+/// Iris from `linfa_datasets`, as in the other crates of the workspace
+pub fn iris() -> Samples {
+    let ds = linfa_datasets::iris();
+    Samples {
+        features: ds.records.outer_iter().map(|row| row.to_vec()).collect(),
+        targets: ds.targets.to_vec(),
+        feature_names: ds.feature_names(),
+        n_classes: 3,
+    }
+}
+
+// Box-Muller transform, rand 0.9 has no normal distribution without rand_distr
+fn gaussian(rng: &mut impl Rng) -> f64 {
+    let u1: f64 = rng.random_range(f64::EPSILON..1.);
+    let u2: f64 = rng.random();
+    (-2. * u1.ln()).sqrt() * (2. * PI * u2).cos()
+}
+
+/// Two interleaving half circles, `n_samples / 2` per class, with gaussian noise of std `noise`
+pub fn moons(n_samples: usize, noise: f64, seed: u64) -> Samples {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut samples = Samples {
+        feature_names: vec!["x".to_string(), "y".to_string()],
+        n_classes: 2,
+        ..Samples::default()
+    };
+    for i in 0..n_samples {
+        let class = i % 2;
+        let t = PI * rng.random::<f64>();
+        let (x, y) = if class == 0 {
+            (t.cos(), t.sin())
+        } else {
+            (1. - t.cos(), 0.5 - t.sin())
+        };
+        samples.features.push(vec![x + noise * gaussian(&mut rng), y + noise * gaussian(&mut rng)]);
+        samples.targets.push(class);
+    }
+    samples
+}
+
+/// A small circle (class 1) inside a large one (class 0), `factor` is the ratio of the radii
+pub fn circles(n_samples: usize, noise: f64, factor: f64, seed: u64) -> Samples {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut samples = Samples {
+        feature_names: vec!["x".to_string(), "y".to_string()],
+        n_classes: 2,
+        ..Samples::default()
+    };
+    for i in 0..n_samples {
+        let class = i % 2;
+        let radius = if class == 0 { 1. } else { factor };
+        let t = 2. * PI * rng.random::<f64>();
+        samples.features.push(vec![
+            radius * t.cos() + noise * gaussian(&mut rng),
+            radius * t.sin() + noise * gaussian(&mut rng),
+        ]);
+        samples.targets.push(class);
+    }
+    samples
+}
+
+// 5x7 bitmaps of the digits 0 to 9, one string per row
+const DIGIT_FONT: [[&str; 7]; 10] = [
+    [".###.", "#...#", "#..##", "#.#.#", "##..#", "#...#", ".###."],
+    ["..#..", ".##..", "..#..", "..#..", "..#..", "..#..", ".###."],
+    [".###.", "#...#", "....#", "...#.", "..#..", ".#...", "#####"],
+    ["#####", "...#.", "..#..", "...#.", "....#", "#...#", ".###."],
+    ["...#.", "..##.", ".#.#.", "#..#.", "#####", "...#.", "...#."],
+    ["#####", "#....", "####.", "....#", "....#", "#...#", ".###."],
+    ["..##.", ".#...", "#....", "####.", "#...#", "#...#", ".###."],
+    ["#####", "....#", "...#.", "..#..", ".#...", ".#...", ".#..."],
+    [".###.", "#...#", "#...#", ".###.", "#...#", "#...#", ".###."],
+    [".###.", "#...#", "#...#", ".####", "....#", "...#.", ".##.."],
+];
+
+/// Side of the square images of `synthetic_digits`
+pub const DIGIT_SIZE: usize = 8;
+
+/// 8x8 grey images of the digits 0 to 9 drawn from a 5x7 font at a random position,
+/// with gaussian noise of std `noise` on every pixel. Pixels are stored row by row
+pub fn synthetic_digits(n_per_class: usize, noise: f64, seed: u64) -> Samples {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut samples = Samples {
+        feature_names: (0..DIGIT_SIZE * DIGIT_SIZE)
+            .map(|i| format!("px{}_{}", i / DIGIT_SIZE, i % DIGIT_SIZE))
+            .collect(),
+        n_classes: DIGIT_FONT.len(),
+        ..Samples::default()
+    };
+    for _ in 0..n_per_class {
+        for (digit, bitmap) in DIGIT_FONT.iter().enumerate() {
+            let top = rng.random_range(0..=DIGIT_SIZE - bitmap.len());
+            let left = rng.random_range(0..=DIGIT_SIZE - bitmap[0].len());
+            let mut pixels: Vec<f64> = (0..DIGIT_SIZE * DIGIT_SIZE).map(|_| noise * gaussian(&mut rng)).collect();
+            for (r, row) in bitmap.iter().enumerate() {
+                for (c, ch) in row.chars().enumerate() {
+                    if ch == '#' {
+                        pixels[(top + r) * DIGIT_SIZE + left + c] += 1.;
+                    }
+                }
+            }
+            samples.features.push(pixels);
+            samples.targets.push(digit);
+        }
+    }
+    samples
+}
+
+/// Sequence for the recurrent networks, one feature vector and one target per time step
+#[derive(Debug, Clone, Default)]
+pub struct Sequence {
+    pub inputs: Vec<Vec<f64>>,
+    pub targets: Vec<usize>,
+}
+
+/// Random bit strings of `length` bits, the target at each step is the parity of the bits seen so far
+pub fn parity_sequences(n_sequences: usize, length: usize, seed: u64) -> Vec<Sequence> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..n_sequences)
+        .map(|_| {
+            let bits: Vec<usize> = (0..length).map(|_| rng.random_range(0..2)).collect();
+            Sequence {
+                inputs: bits.iter().map(|&b| vec![b as f64]).collect(),
+                targets: bits.iter()
+                    .scan(0, |parity, &b| {
+                        *parity ^= b;
+                        Some(*parity)
+                    })
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_uses_the_training_statistics() {
+        let (mut train, mut test) = iris().split_with_ratio(0.8, 1);
+        let raw_test = test.clone();
+        let stats = train.standardize();
+        stats.apply(&mut test);
+        for j in 0..train.n_features() {
+            let mean = train.features.iter().map(|x| x[j]).sum::<f64>() / train.len() as f64;
+            assert!(mean.abs() < 1e-12);
+        }
+        for (x, raw) in test.features.iter().zip(raw_test.features.iter()) {
+            for j in 0..x.len() {
+                assert!((x[j] * stats.std[j] + stats.mean[j] - raw[j]).abs() < 1e-12);
+            }
+        }
+    }
+}
@@ -0,0 +1,386 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use uuid::Uuid;
+
+use super::nn;
+use super::optim::{accumulate_gradients, Gradients};
+use super::{Op, Value};
+
+/// Called with a node of the graph before its gradient is propagated to its children.
+/// A node used several times, e.g. a parameter, has a copy in the graph for each use: the hook
+/// is called once per copy with the part of the gradient flowing through that copy, the full
+/// gradient is their sum as computed by `optim::accumulate_gradients`
+pub type BackwardHook = Box<dyn FnMut(&Value)>;
+
+/// Options of `backward_with`: hooks registered per node id and anomaly detection
+#[derive(Default)]
+pub struct BackwardOptions {
+    detect_anomaly: bool,
+    hooks: HashMap<Uuid, Vec<BackwardHook>>,
+}
+
+impl BackwardOptions {
+    pub fn new() -> BackwardOptions {
+        BackwardOptions::default()
+    }
+
+    /// Stop at the first non finite data or gradient instead of propagating it
+    pub fn detect_anomaly(mut self, detect: bool) -> BackwardOptions {
+        self.detect_anomaly = detect;
+        self
+    }
+
+    /// Registers `hook` on every node with id `id`, copies of a node in the graph share the id
+    /// so the hook runs for each of them
+    pub fn register_hook(&mut self, id: Uuid, hook: impl FnMut(&Value) + 'static) {
+        self.hooks.entry(id).or_default().push(Box::new(hook));
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AnomalyKind {
+    NonFiniteData,
+    NonFiniteGrad,
+}
+
+/// First non finite value met, with the path of nodes leading to it from the root
+#[derive(Debug, Clone)]
+pub struct Anomaly {
+    pub kind: AnomalyKind,
+    pub label: String,
+    pub op: Op,
+    pub value: f64,
+    // root first, the offending node last
+    pub ancestry: Vec<String>,
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let what = match self.kind {
+            AnomalyKind::NonFiniteData => "data",
+            AnomalyKind::NonFiniteGrad => "grad",
+        };
+        write!(f, "non finite {} {} in node `{}` ({:?}), ancestry: {}",
+            what, self.value, self.label, self.op, self.ancestry.join(" <- "))
+    }
+}
+
+impl Error for Anomaly {}
+
+fn describe(value: &Value) -> String {
+    format!("{} ({:?})", value.label, value.op)
+}
+
+fn anomaly(kind: AnomalyKind, node: &Value, number: f64, path: &[String]) -> Anomaly {
+    let mut ancestry = path.to_vec();
+    ancestry.push(describe(node));
+    Anomaly { kind, label: node.label.clone(), op: node.op.clone(), value: number, ancestry }
+}
+
+/// Finds the node where a non finite value was first produced during the forward pass,
+/// i.e. a node whose data is not finite while the data of all its children is
+fn find_data_anomaly(node: &Value, path: &mut Vec<String>) -> Option<Anomaly> {
+    if node.data.is_finite() {
+        return None;
+    }
+    path.push(describe(node));
+    for child in node.children.iter() {
+        if let Some(found) = find_data_anomaly(child, path) {
+            return Some(found);
+        }
+    }
+    path.pop();
+    Some(anomaly(AnomalyKind::NonFiniteData, node, node.data, path))
+}
+
+fn backward_node(node: &mut Value, options: &mut BackwardOptions, path: &mut Vec<String>) -> Result<(), Anomaly> {
+    if let Some(hooks) = options.hooks.get_mut(&node._id) {
+        for hook in hooks.iter_mut() {
+            hook(node);
+        }
+    }
+    node._backward();
+
+    path.push(describe(node));
+    if options.detect_anomaly {
+        if let Some(child) = node.children.iter().find(|c| !c.grad.is_finite()) {
+            return Err(anomaly(AnomalyKind::NonFiniteGrad, child, child.grad, path));
+        }
+    }
+    for child in node.children.iter_mut() {
+        backward_node(child, options, path)?;
+    }
+    path.pop();
+    Ok(())
+}
+
+/// Same as `Value::backward`, calling the registered hooks on each node and, in anomaly mode,
+/// returning the first node with non finite data or gradient instead of propagating it
+pub fn backward_with(root: &mut Value, options: &mut BackwardOptions) -> Result<(), Anomaly> {
+    if options.detect_anomaly {
+        if let Some(found) = find_data_anomaly(root, &mut vec![]) {
+            return Err(found);
+        }
+    }
+    root.set_gradient(1.0);
+    if options.detect_anomaly && !root.grad.is_finite() {
+        return Err(anomaly(AnomalyKind::NonFiniteGrad, root, root.grad, &[]));
+    }
+    backward_node(root, options, &mut vec![])
+}
+
+/// Gradient of a labelled node, summed over all of its copies in the graph
+#[derive(Debug, Clone)]
+pub struct GradientEntry {
+    pub label: String,
+    pub op: Op,
+    pub data: f64,
+    pub grad: f64,
+    // number of times the node appears in the graph
+    pub uses: usize,
+}
+
+/// Gradient magnitude of every labelled node, used to spot vanishing or exploding gradients
+#[derive(Debug, Clone)]
+pub struct GradientReport {
+    pub entries: Vec<GradientEntry>,
+    pub vanishing_threshold: f64,
+    pub exploding_threshold: f64,
+}
+
+impl GradientReport {
+    pub fn vanishing(&self) -> impl Iterator<Item = &GradientEntry> {
+        self.entries.iter().filter(|e| e.grad.abs() < self.vanishing_threshold)
+    }
+
+    pub fn exploding(&self) -> impl Iterator<Item = &GradientEntry> {
+        self.entries.iter().filter(|e| !e.grad.is_finite() || e.grad.abs() > self.exploding_threshold)
+    }
+}
+
+impl fmt::Display for GradientReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:<16} {:<8} {:>6} {:>14} {:>14}", "node", "op", "uses", "data", "|grad|")?;
+        for entry in self.entries.iter() {
+            let magnitude = entry.grad.abs();
+            let flag = if !magnitude.is_finite() || magnitude > self.exploding_threshold {
+                "  exploding"
+            } else if magnitude < self.vanishing_threshold {
+                "  vanishing"
+            } else {
+                ""
+            };
+            writeln!(f, "{:<16} {:<8} {:>6} {:>14.6e} {:>14.6e}{}",
+                entry.label, format!("{:?}", entry.op), entry.uses, entry.data, magnitude, flag)?;
+        }
+        Ok(())
+    }
+}
+
+fn collect_gradients(node: &Value, entries: &mut Vec<GradientEntry>, index: &mut HashMap<Uuid, usize>) {
+    // constants created for scalar operands are not interesting to look at
+    if !node.label.is_empty() && !node.is_constant() {
+        match index.get(&node._id) {
+            Some(&i) => {
+                entries[i].grad += node.grad;
+                entries[i].uses += 1;
+            }
+            None => {
+                index.insert(node._id, entries.len());
+                entries.push(GradientEntry {
+                    label: node.label.clone(),
+                    op: node.op.clone(),
+                    data: node.data,
+                    grad: node.grad,
+                    uses: 1,
+                });
+            }
+        }
+    }
+    for child in node.children.iter() {
+        collect_gradients(child, entries, index);
+    }
+}
+
+/// Builds the gradient report of the labelled nodes of `root`, after `backward` was called
+pub fn gradient_report(root: &Value) -> GradientReport {
+    let mut entries = Vec::new();
+    collect_gradients(root, &mut entries, &mut HashMap::new());
+    GradientReport { entries, vanishing_threshold: 1e-7, exploding_threshold: 1e3 }
+}
+
+/// Number of nodes held by a graph and an estimate of the memory they take
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GraphSize {
+    pub nodes: usize,
+    pub bytes: usize,
+}
+
+impl fmt::Display for GraphSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} nodes, {:.2} MiB", self.nodes, self.bytes as f64 / (1024. * 1024.))
+    }
+}
+
+/// Size of the graph of `root`, every node is counted with its label, copies of a node
+/// are counted again as they are stored again
+pub fn graph_size(root: &Value) -> GraphSize {
+    let mut size = GraphSize::default();
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        size.nodes += 1;
+        size.bytes += std::mem::size_of::<Value>() + node.label.capacity();
+        stack.extend(node.children.iter());
+    }
+    size
+}
+
+/// Gradients from `backward` next to central finite differences, for each input
+#[derive(Debug, Clone)]
+pub struct GradientCheck {
+    pub analytic: Vec<f64>,
+    pub numeric: Vec<f64>,
+    pub max_abs_error: f64,
+    // |analytic - numeric| / (|analytic| + |numeric|), the largest over the inputs
+    pub max_rel_error: f64,
+}
+
+impl GradientCheck {
+    pub fn passed(&self, tolerance: f64) -> bool {
+        self.max_abs_error < tolerance || self.max_rel_error < tolerance
+    }
+}
+
+/// Compares the gradient of the scalar function `f` at `point` computed by back propagation
+/// with the one estimated as `(f(x + eps) - f(x - eps)) / 2 eps` for each input
+pub fn check_gradients(f: impl Fn(&[Value]) -> Value, point: &[f64], eps: f64) -> GradientCheck {
+    let leaves = nn::inputs(point);
+    let mut out = f(&leaves);
+    out.backward();
+    let mut grads = Gradients::new();
+    accumulate_gradients(&out, &mut grads);
+    let analytic: Vec<f64> = leaves.iter().map(|l| grads.get(&l._id).copied().unwrap_or_default()).collect();
+
+    let numeric: Vec<f64> = (0..point.len())
+        .map(|i| {
+            let mut shifted = point.to_vec();
+            shifted[i] = point[i] + eps;
+            let plus = f(&nn::inputs(&shifted)).data;
+            shifted[i] = point[i] - eps;
+            let minus = f(&nn::inputs(&shifted)).data;
+            (plus - minus) / (2. * eps)
+        })
+        .collect();
+
+    let (mut max_abs_error, mut max_rel_error) = (0f64, 0f64);
+    for (a, n) in analytic.iter().zip(numeric.iter()) {
+        let abs_error = (a - n).abs();
+        max_abs_error = max_abs_error.max(abs_error);
+        max_rel_error = max_rel_error.max(abs_error / (a.abs() + n.abs()).max(1e-12));
+    }
+    GradientCheck { analytic, numeric, max_abs_error, max_rel_error }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use num_traits::Pow;
+
+    use super::*;
+    use crate::parser::parse_graph;
+
+    fn collect_ids(node: &Value, ids: &mut Vec<Uuid>) {
+        ids.push(node._id);
+        for child in node.children.iter() {
+            collect_ids(child, ids);
+        }
+    }
+
+    #[test]
+    fn non_finite_gradient_names_the_node() {
+        // sqrt is finite at 0 but its derivative isn't
+        let mut root = Value::leaf("x", 0.).pow(0.5) * 2;
+        root.set_label("L");
+        let mut options = BackwardOptions::new().detect_anomaly(true);
+        let found = backward_with(&mut root, &mut options).unwrap_err();
+        assert_eq!(found.kind, AnomalyKind::NonFiniteGrad);
+        assert_eq!(found.label, "x");
+        assert_eq!(found.op, Op::NoOp);
+        assert_eq!(found.value, f64::INFINITY);
+        assert_eq!(found.ancestry, vec!["L (Mult)", " (Pow)", "x (NoOp)"]);
+        // without anomaly mode the infinity goes through
+        let mut root = Value::leaf("x", 0.).pow(0.5) * 2;
+        assert!(backward_with(&mut root, &mut BackwardOptions::new()).is_ok());
+    }
+
+    #[test]
+    fn non_finite_data_names_the_op_that_produced_it() {
+        let bindings = HashMap::from([("a", 1.0), ("b", 2.0)]);
+        let mut graph = parse_graph("L = ln(a - a) * b", &bindings).unwrap();
+        let found = backward_with(&mut graph.root, &mut BackwardOptions::new().detect_anomaly(true)).unwrap_err();
+        assert_eq!(found.kind, AnomalyKind::NonFiniteData);
+        assert_eq!((found.label.as_str(), &found.op), ("t1", &Op::Log));
+        assert_eq!(found.ancestry, vec!["L (Mult)", "t1 (Log)"]);
+        assert!(found.to_string().contains("node `t1` (Log)"), "{}", found);
+        // nothing was propagated
+        assert_eq!(graph.root.grad(), 0.);
+    }
+
+    #[test]
+    fn hooks_fire_once_per_node_parents_first() {
+        let bindings = HashMap::from([("a", 2.0), ("b", 3.0), ("d", 4.0)]);
+        let mut graph = parse_graph("c = a + b; L = c * d", &bindings).unwrap();
+        let seen = Rc::new(RefCell::new(vec![]));
+        let mut options = BackwardOptions::new();
+        let mut ids = vec![];
+        collect_ids(&graph.root, &mut ids);
+        for id in ids {
+            let seen = Rc::clone(&seen);
+            options.register_hook(id, move |node| seen.borrow_mut().push((node.label().to_string(), node.grad())));
+        }
+        backward_with(&mut graph.root, &mut options).unwrap();
+        // no node is used twice, so each is seen once with its complete gradient, before its children
+        let expected: Vec<(String, f64)> = [("L", 1.), ("c", 4.), ("a", 4.), ("b", 4.), ("d", 5.)]
+            .iter()
+            .map(|(l, g)| (l.to_string(), *g))
+            .collect();
+        assert_eq!(*seen.borrow(), expected);
+    }
+
+    #[test]
+    fn hooks_fire_for_every_copy_of_a_shared_leaf() {
+        let bindings = HashMap::from([("a", 2.0), ("b", 3.0)]);
+        let mut graph = parse_graph("c = a * b; L = c + a", &bindings).unwrap();
+        let grads = Rc::new(RefCell::new(vec![]));
+        let mut options = BackwardOptions::new();
+        let seen = Rc::clone(&grads);
+        options.register_hook(graph.leaves["a"].id(), move |node| seen.borrow_mut().push(node.grad()));
+        backward_with(&mut graph.root, &mut options).unwrap();
+        // dL/da = b through the product and 1 through the sum, each copy only sees its own part
+        let mut parts = grads.borrow().clone();
+        parts.sort_by(f64::total_cmp);
+        assert_eq!(parts, vec![1., 3.]);
+        let mut total = Gradients::new();
+        accumulate_gradients(&graph.root, &mut total);
+        assert_eq!(total[&graph.leaves["a"].id()], parts.iter().sum::<f64>());
+    }
+
+    #[test]
+    fn report_matches_hand_computed_gradients() {
+        let bindings = HashMap::from([("a", 2.0), ("b", 3.0)]);
+        let mut graph = parse_graph("c = a * b; L = c + a", &bindings).unwrap();
+        graph.root.backward();
+        let report = gradient_report(&graph.root);
+        let rows: Vec<(&str, f64, f64, usize)> = report.entries.iter()
+            .map(|e| (e.label.as_str(), e.data, e.grad, e.uses))
+            .collect();
+        // L = ab + a: dL/dc = 1, dL/da = b + 1 over its two uses, dL/db = a
+        assert_eq!(rows, vec![("L", 8., 1., 1), ("c", 6., 1., 1), ("a", 2., 4., 2), ("b", 3., 2., 1)]);
+        assert_eq!(report.vanishing().count(), 0);
+        assert_eq!(report.exploding().count(), 0);
+    }
+}
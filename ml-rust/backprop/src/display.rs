@@ -0,0 +1,180 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use uuid::Uuid;
+
+use super::diagnostics::graph_size;
+use super::{Op, Value};
+
+fn op_symbol(op: &Op) -> &'static str {
+    match op {
+        Op::Add => "+",
+        Op::Mult => "*",
+        Op::Sub => "-",
+        Op::Div => "/",
+        Op::Pow => "^",
+        Op::Tanh => "tanh",
+        Op::Sigmoid => "sigmoid",
+        Op::ReLU => "relu",
+        Op::Exp => "exp",
+        Op::Log => "ln",
+        Op::Abs => "abs",
+        Op::Max => "max",
+        Op::Checkpoint => "checkpoint",
+        Op::NoOp => "",
+    }
+}
+
+// name of a node, its id when it has no label
+fn node_name(value: &Value) -> String {
+    if value.is_constant() {
+        format!("{}", value.data)
+    } else if value.label.is_empty() {
+        format!("#{}", value._id.as_u128())
+    } else {
+        value.label.clone()
+    }
+}
+
+/// Indented tree view of a graph for the terminal, one node per line with its op, data and grad.
+/// A parameter is copied in the graph each time it is used, copies after the first one
+/// are marked as shared and their subtree isn't repeated
+pub struct Tree<'a> {
+    root: &'a Value,
+    max_depth: Option<usize>,
+    precision: usize,
+}
+
+impl<'a> Tree<'a> {
+    /// Nodes deeper than `depth` are summarised by their number, the root is at depth 0
+    pub fn max_depth(mut self, depth: usize) -> Tree<'a> {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Digits printed after the decimal point, 4 by default
+    pub fn precision(mut self, precision: usize) -> Tree<'a> {
+        self.precision = precision;
+        self
+    }
+
+    fn write_node(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        node: &Value,
+        prefix: &str,
+        is_last: bool,
+        depth: usize,
+        seen: &mut HashSet<Uuid>,
+    ) -> fmt::Result {
+        let (branch, indent) = match (depth, is_last) {
+            (0, _) => ("", ""),
+            (_, true) => ("└── ", "    "),
+            (_, false) => ("├── ", "│   "),
+        };
+        write!(f, "{}{}", prefix, branch)?;
+        if node.op != Op::NoOp {
+            write!(f, "{} ", op_symbol(&node.op))?;
+        }
+        write!(f, "{} data={:.p$} grad={:.p$}", node_name(node), node.data, node.grad, p = self.precision)?;
+        // constants are created for every operand, marking them would only add noise
+        if !node.is_constant() && !seen.insert(node._id) {
+            return writeln!(f, " [shared]");
+        }
+        writeln!(f)?;
+
+        let child_prefix = format!("{}{}", prefix, indent);
+        if self.max_depth.is_some_and(|max| depth >= max) && !node.children.is_empty() {
+            let hidden: usize = node.children.iter().map(|c| graph_size(c).nodes).sum();
+            return writeln!(f, "{}└── … {} more nodes", child_prefix, hidden);
+        }
+        for (i, child) in node.children.iter().enumerate() {
+            self.write_node(f, child, &child_prefix, i + 1 == node.children.len(), depth + 1, seen)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Tree<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_node(f, self.root, "", true, 0, &mut HashSet::new())
+    }
+}
+
+// binding strength used to decide where parentheses are needed
+fn precedence(value: &Value) -> u8 {
+    match value.op {
+        Op::Add | Op::Sub => 1,
+        Op::Mult | Op::Div => 2,
+        Op::Pow => 4,
+        _ if value.is_constant() && value.data < 0. => 3,
+        _ => 5,
+    }
+}
+
+fn infix_operand(value: &Value, min_precedence: u8) -> String {
+    if precedence(value) < min_precedence {
+        format!("({})", value.to_infix())
+    } else {
+        value.to_infix()
+    }
+}
+
+impl Value {
+    /// Tree view of the graph, `println!("{}", value)` prints it without a depth limit
+    pub fn tree(&self) -> Tree<'_> {
+        Tree { root: self, max_depth: None, precision: 4 }
+    }
+
+    /// One line infix expression of the graph, e.g. `(a + b) * d^2`, with leaves
+    /// written by label and constants by value
+    pub fn to_infix(&self) -> String {
+        let operand = |i: usize, min_precedence: u8| infix_operand(&self.children[i], min_precedence);
+        match self.op {
+            Op::NoOp => node_name(self),
+            Op::Add => format!("{} + {}", operand(0, 1), operand(1, 1)),
+            Op::Sub => format!("{} - {}", operand(0, 1), operand(1, 2)),
+            Op::Mult => format!("{} * {}", operand(0, 2), operand(1, 3)),
+            Op::Div => format!("{} / {}", operand(0, 2), operand(1, 3)),
+            Op::Pow => format!("{}^{}", operand(0, 5), operand(1, 5)),
+            Op::Checkpoint if self.recompute.is_some() => {
+                self.expand_checkpoint().expect("checkpoint with a recompute function").to_infix()
+            }
+            Op::Max | Op::Checkpoint => {
+                let arguments: Vec<String> = self.children.iter().map(Value::to_infix).collect();
+                format!("{}({})", op_symbol(&self.op), arguments.join(", "))
+            }
+            ref op => format!("{}({})", op_symbol(op), self.children[0].to_infix()),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.tree())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::parser::parse_graph;
+
+    #[test]
+    fn tree_and_infix() {
+        let bindings = HashMap::from([("a", 2.0), ("b", -3.0)]);
+        let l = parse_graph("c = a + b; L = c * a / 2", &bindings).unwrap().root;
+        assert_eq!(l.to_infix(), "(a + b) * a / 2");
+
+        let tree = l.to_string();
+        let lines: Vec<&str> = tree.lines().collect();
+        assert_eq!(lines[0], "/ L data=-1.0000 grad=0.0000");
+        // `a` is used twice, its second copy is marked
+        assert_eq!(lines.iter().filter(|l| l.contains(" a data=")).count(), 2);
+        assert_eq!(lines.iter().filter(|l| l.ends_with("[shared]")).count(), 1);
+
+        let shallow = l.tree().max_depth(1).to_string();
+        assert!(shallow.lines().any(|l| l.ends_with("… 4 more nodes")), "{}", shallow);
+    }
+}
@@ -0,0 +1,553 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+
+use prost::Message;
+
+use super::nn::{Activation, Layer, MLP};
+
+/// The subset of the ONNX protobuf messages needed to describe an MLP, with the field
+/// numbers of `onnx.proto` so that any ONNX runtime can read the files
+pub mod onnx {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ModelProto {
+        #[prost(int64, tag = "1")]
+        pub ir_version: i64,
+        #[prost(string, tag = "2")]
+        pub producer_name: String,
+        #[prost(string, tag = "3")]
+        pub producer_version: String,
+        #[prost(message, optional, tag = "7")]
+        pub graph: Option<GraphProto>,
+        #[prost(message, repeated, tag = "8")]
+        pub opset_import: Vec<OperatorSetIdProto>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct OperatorSetIdProto {
+        #[prost(string, tag = "1")]
+        pub domain: String,
+        #[prost(int64, tag = "2")]
+        pub version: i64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct GraphProto {
+        #[prost(message, repeated, tag = "1")]
+        pub node: Vec<NodeProto>,
+        #[prost(string, tag = "2")]
+        pub name: String,
+        #[prost(message, repeated, tag = "5")]
+        pub initializer: Vec<TensorProto>,
+        #[prost(message, repeated, tag = "11")]
+        pub input: Vec<ValueInfoProto>,
+        #[prost(message, repeated, tag = "12")]
+        pub output: Vec<ValueInfoProto>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct NodeProto {
+        #[prost(string, repeated, tag = "1")]
+        pub input: Vec<String>,
+        #[prost(string, repeated, tag = "2")]
+        pub output: Vec<String>,
+        #[prost(string, tag = "3")]
+        pub name: String,
+        #[prost(string, tag = "4")]
+        pub op_type: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct TensorProto {
+        #[prost(int64, repeated, tag = "1")]
+        pub dims: Vec<i64>,
+        #[prost(int32, tag = "2")]
+        pub data_type: i32,
+        #[prost(float, repeated, tag = "4")]
+        pub float_data: Vec<f32>,
+        #[prost(string, tag = "8")]
+        pub name: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ValueInfoProto {
+        #[prost(string, tag = "1")]
+        pub name: String,
+        #[prost(message, optional, tag = "2")]
+        pub r#type: Option<TypeProto>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct TypeProto {
+        #[prost(message, optional, tag = "1")]
+        pub tensor_type: Option<TensorTypeProto>,
+    }
+
+    /// `TypeProto.Tensor` in `onnx.proto`
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct TensorTypeProto {
+        #[prost(int32, tag = "1")]
+        pub elem_type: i32,
+        #[prost(message, optional, tag = "2")]
+        pub shape: Option<TensorShapeProto>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct TensorShapeProto {
+        #[prost(message, repeated, tag = "1")]
+        pub dim: Vec<Dimension>,
+    }
+
+    /// `TensorShapeProto.Dimension`, either a fixed size or a named one like `batch`
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Dimension {
+        #[prost(int64, optional, tag = "1")]
+        pub dim_value: Option<i64>,
+        #[prost(string, optional, tag = "2")]
+        pub dim_param: Option<String>,
+    }
+
+    // TensorProto.DataType.FLOAT
+    pub const FLOAT: i32 = 1;
+}
+
+const IR_VERSION: i64 = 8;
+const OPSET_VERSION: i64 = 13;
+
+#[derive(Debug)]
+pub struct InvalidModel {
+    pub message: String,
+}
+
+impl fmt::Display for InvalidModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid model: {}", self.message)
+    }
+}
+
+impl Error for InvalidModel {}
+
+fn invalid(message: String) -> Box<dyn Error> {
+    Box::new(InvalidModel { message })
+}
+
+fn activation_op(activation: Activation) -> Option<&'static str> {
+    match activation {
+        Activation::Tanh => Some("Tanh"),
+        Activation::ReLU => Some("Relu"),
+        Activation::Sigmoid => Some("Sigmoid"),
+        Activation::Linear => None,
+    }
+}
+
+fn tensor_info(name: &str, features: usize) -> onnx::ValueInfoProto {
+    let dim = |dim_value: Option<i64>, dim_param: Option<&str>| onnx::Dimension {
+        dim_value,
+        dim_param: dim_param.map(str::to_string),
+    };
+    onnx::ValueInfoProto {
+        name: name.to_string(),
+        r#type: Some(onnx::TypeProto {
+            tensor_type: Some(onnx::TensorTypeProto {
+                elem_type: onnx::FLOAT,
+                shape: Some(onnx::TensorShapeProto {
+                    dim: vec![dim(None, Some("batch")), dim(Some(features as i64), None)],
+                }),
+            }),
+        }),
+    }
+}
+
+fn node(op_type: &str, inputs: &[&str], output: &str) -> onnx::NodeProto {
+    onnx::NodeProto {
+        input: inputs.iter().map(|i| i.to_string()).collect(),
+        output: vec![output.to_string()],
+        name: output.to_string(),
+        op_type: op_type.to_string(),
+    }
+}
+
+// weights as a `[n_inputs, n_outputs]` matrix for `MatMul`, i.e. transposed
+fn layer_tensors(layer: &Layer, index: usize) -> (onnx::TensorProto, onnx::TensorProto) {
+    let neurons = layer.neurons();
+    let n_inputs = neurons[0].weights().len();
+    let weights = (0..n_inputs)
+        .flat_map(|i| neurons.iter().map(move |n| n.weights()[i].data() as f32))
+        .collect();
+    let bias = neurons.iter().map(|n| n.bias().data() as f32).collect();
+    (
+        onnx::TensorProto {
+            dims: vec![n_inputs as i64, neurons.len() as i64],
+            data_type: onnx::FLOAT,
+            float_data: weights,
+            name: format!("W{}", index),
+        },
+        onnx::TensorProto {
+            dims: vec![neurons.len() as i64],
+            data_type: onnx::FLOAT,
+            float_data: bias,
+            name: format!("b{}", index),
+        },
+    )
+}
+
+/// ONNX graph of the MLP: a `MatMul` and an `Add` per layer followed by its activation,
+/// from the `input` tensor of shape `[batch, n_inputs]` to the `logits`
+pub fn mlp_to_onnx(mlp: &MLP) -> onnx::ModelProto {
+    let mut graph = onnx::GraphProto {
+        name: "mlp".to_string(),
+        input: vec![tensor_info("input", mlp.layer_sizes()[0])],
+        output: vec![tensor_info("logits", *mlp.layer_sizes().last().expect("at least one layer"))],
+        ..Default::default()
+    };
+    let n_layers = mlp.layers().len();
+    let mut previous = "input".to_string();
+    for (i, layer) in mlp.layers().iter().enumerate() {
+        let (weights, bias) = layer_tensors(layer, i);
+        let activation = activation_op(layer.neurons()[0].activation());
+        let output = |name: &str| if i + 1 == n_layers { "logits".to_string() } else { format!("{}{}", name, i) };
+        let pre_activation = if activation.is_some() { format!("z{}", i) } else { output("z") };
+
+        graph.node.push(node("MatMul", &[&previous, &weights.name], &format!("xW{}", i)));
+        graph.node.push(node("Add", &[&format!("xW{}", i), &bias.name], &pre_activation));
+        previous = match activation {
+            Some(op_type) => {
+                let activated = output("h");
+                graph.node.push(node(op_type, &[&pre_activation], &activated));
+                activated
+            }
+            None => pre_activation,
+        };
+        graph.initializer.extend([weights, bias]);
+    }
+    onnx::ModelProto {
+        ir_version: IR_VERSION,
+        producer_name: "backprop".to_string(),
+        producer_version: env!("CARGO_PKG_VERSION").to_string(),
+        graph: Some(graph),
+        opset_import: vec![onnx::OperatorSetIdProto { domain: String::new(), version: OPSET_VERSION }],
+    }
+}
+
+/// Runs the graph of an ONNX model on a single sample, only the operators written
+/// by `mlp_to_onnx` are supported
+pub fn onnx_predict(model: &onnx::ModelProto, x: &[f64]) -> Result<Vec<f64>, Box<dyn Error>> {
+    let graph = model.graph.as_ref().ok_or_else(|| invalid("no graph".to_string()))?;
+    let mut tensors: HashMap<&str, (Vec<i64>, Vec<f64>)> = graph.initializer.iter()
+        .map(|t| (t.name.as_str(), (t.dims.clone(), t.float_data.iter().map(|&v| v as f64).collect())))
+        .collect();
+    let input = graph.input.first().ok_or_else(|| invalid("no input".to_string()))?;
+    tensors.insert(&input.name, (vec![x.len() as i64], x.to_vec()));
+
+    for node in graph.node.iter() {
+        let operand = |i: usize| {
+            node.input.get(i)
+                .and_then(|name| tensors.get(name.as_str()))
+                .ok_or_else(|| invalid(format!("missing input {} of {}", i, node.name)))
+        };
+        let (a_dims, a) = operand(0)?;
+        let result = match node.op_type.as_str() {
+            "MatMul" => {
+                let (b_dims, b) = operand(1)?;
+                let (rows, cols) = (b_dims[0] as usize, b_dims[1] as usize);
+                if a.len() != rows {
+                    return Err(invalid(format!("{} multiplies {} values by {} rows", node.name, a.len(), rows)));
+                }
+                let out = (0..cols).map(|j| (0..rows).map(|i| a[i] * b[i * cols + j]).sum()).collect();
+                (vec![cols as i64], out)
+            }
+            "Add" => {
+                let (_, b) = operand(1)?;
+                (a_dims.clone(), a.iter().zip(b.iter()).map(|(a, b)| a + b).collect())
+            }
+            "Tanh" => (a_dims.clone(), a.iter().map(|v| v.tanh()).collect()),
+            "Relu" => (a_dims.clone(), a.iter().map(|v| v.max(0.)).collect()),
+            "Sigmoid" => (a_dims.clone(), a.iter().map(|v| 1. / (1. + (-v).exp())).collect()),
+            other => return Err(invalid(format!("unsupported operator {}", other))),
+        };
+        tensors.insert(&node.output[0], result);
+    }
+    let output = graph.output.first().ok_or_else(|| invalid("no output".to_string()))?;
+    tensors.remove(output.name.as_str())
+        .map(|(_, values)| values)
+        .ok_or_else(|| invalid(format!("output {} is never computed", output.name)))
+}
+
+/// Writes the MLP as an ONNX file, then reads it back and checks that it decodes to the same
+/// model and gives the same outputs as the network, up to the precision of `f32`
+pub fn save_onnx(mlp: &MLP, file_path: &str) -> Result<(), Box<dyn Error>> {
+    let model = mlp_to_onnx(mlp);
+    fs::write(file_path, model.encode_to_vec())?;
+
+    let decoded = onnx::ModelProto::decode(fs::read(file_path)?.as_slice())?;
+    if decoded != model {
+        return Err(invalid(format!("{} doesn't decode to the written model", file_path)));
+    }
+    let probe: Vec<f64> = (0..mlp.layer_sizes()[0]).map(|i| (i as f64 * 0.7).sin()).collect();
+    let expected = mlp.predict(&probe);
+    let outputs = onnx_predict(&decoded, &probe)?;
+    let max_error = expected.iter().zip(outputs.iter()).map(|(a, b)| (a - b).abs()).fold(0., f64::max);
+    if outputs.len() != expected.len() || max_error > 1e-4 {
+        return Err(invalid(format!("{} gives {:?} instead of {:?}", file_path, outputs, expected)));
+    }
+    Ok(())
+}
+
+fn rust_array(values: impl Iterator<Item = String>) -> String {
+    format!("[{}]", values.collect::<Vec<_>>().join(", "))
+}
+
+fn rust_activation(activation: Activation) -> &'static str {
+    match activation {
+        Activation::Tanh => "f64::tanh",
+        Activation::ReLU => "|x| x.max(0.)",
+        Activation::Sigmoid => "|x| 1. / (1. + (-x).exp())",
+        Activation::Linear => "|x| x",
+    }
+}
+
+/// Source of a Rust module with the weights of the MLP as constants and a `predict` function,
+/// which needs neither this crate nor any other dependency
+pub fn mlp_to_rust(mlp: &MLP) -> String {
+    let sizes = mlp.layer_sizes();
+    let mut source = format!(
+        "//! MLP {:?} exported by `backprop::export::mlp_to_rust`, do not edit\n\
+         #![allow(clippy::excessive_precision, clippy::unreadable_literal)]\n\n\
+         pub const INPUTS: usize = {};\npub const OUTPUTS: usize = {};\n\n",
+        sizes, sizes[0], sizes[sizes.len() - 1]
+    );
+    for (i, layer) in mlp.layers().iter().enumerate() {
+        let neurons = layer.neurons();
+        let n_inputs = neurons[0].weights().len();
+        let rows = neurons.iter().map(|n| rust_array(n.weights().iter().map(|w| format!("{:?}", w.data()))));
+        source.push_str(&format!(
+            "const W{}: [[f64; {}]; {}] = {};\nconst B{}: [f64; {}] = {};\n\n",
+            i, n_inputs, neurons.len(), rust_array(rows),
+            i, neurons.len(), rust_array(neurons.iter().map(|n| format!("{:?}", n.bias().data()))),
+        ));
+    }
+    source.push_str(
+        "fn dense<const I: usize, const O: usize>(\n    \
+             w: &[[f64; I]; O],\n    b: &[f64; O],\n    x: &[f64; I],\n    activation: fn(f64) -> f64,\n\
+         ) -> [f64; O] {\n    \
+             std::array::from_fn(|j| activation(b[j] + w[j].iter().zip(x.iter()).map(|(w, x)| w * x).sum::<f64>()))\n\
+         }\n\n\
+         /// Output logits of the network for a sample\n\
+         pub fn predict(x: &[f64; INPUTS]) -> [f64; OUTPUTS] {\n",
+    );
+    let n_layers = mlp.layers().len();
+    for (i, layer) in mlp.layers().iter().enumerate() {
+        let input = if i == 0 { "x".to_string() } else { format!("&h{}", i - 1) };
+        let call = format!("dense(&W{}, &B{}, {}, {})", i, i, input, rust_activation(layer.neurons()[0].activation()));
+        if i + 1 == n_layers {
+            source.push_str(&format!("    {}\n}}\n", call));
+        } else {
+            source.push_str(&format!("    let h{} = {};\n", i, call));
+        }
+    }
+    source.push_str(
+        "\n/// Index of the largest logit, i.e. the predicted class\n\
+         pub fn predict_class(x: &[f64; INPUTS]) -> usize {\n    \
+             let logits = predict(x);\n    \
+             (0..OUTPUTS).fold(0, |best, i| if logits[i] > logits[best] { i } else { best })\n\
+         }\n",
+    );
+    source
+}
+
+pub fn save_rust_module(mlp: &MLP, file_path: &str) -> Result<(), Box<dyn Error>> {
+    fs::write(file_path, mlp_to_rust(mlp))?;
+    Ok(())
+}
+
+// output of `mlp_to_rust` for `snapshot_mlp`, compiled with the tests so that it can be run
+#[cfg(test)]
+#[path = "snapshots/exported_mlp.rs"]
+mod exported_mlp;
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::nn::argmax;
+
+    // weights that are exact binary fractions, so that the snapshot is short and stable
+    fn snapshot_mlp() -> MLP {
+        let mut mlp = MLP::zeros(&[3, 4, 2], Activation::Tanh);
+        for (i, param) in mlp.parameters_mut().into_iter().enumerate() {
+            param.set_data(((i * 7) % 13) as f64 / 8. - 0.75);
+        }
+        mlp
+    }
+
+    #[test]
+    fn rust_module_matches_the_snapshot_and_the_network() {
+        let mlp = snapshot_mlp();
+        // regenerate with `fs::write("src/snapshots/exported_mlp.rs", mlp_to_rust(&mlp))` when the export changes
+        assert_eq!(mlp_to_rust(&mlp), include_str!("snapshots/exported_mlp.rs"));
+
+        for x in [[0.3, -1.0, 2.5], [0., 0., 0.], [-2., 0.7, 1.1]] {
+            let expected = mlp.predict(&x);
+            let logits = super::exported_mlp::predict(&x);
+            for (a, b) in expected.iter().zip(logits.iter()) {
+                assert!((a - b).abs() < 1e-12, "{:?} != {:?}", logits, expected);
+            }
+            assert_eq!(super::exported_mlp::predict_class(&x), argmax(&expected));
+        }
+    }
+
+    // a field of a protobuf message, read with the wire format rather than the prost structs
+    // so that the field numbers and types are checked against `onnx.proto` itself
+    #[derive(Debug, PartialEq)]
+    enum Field<'a> {
+        Varint(u64),
+        Bytes(&'a [u8]),
+    }
+
+    fn varint(bytes: &[u8], pos: &mut usize) -> u64 {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = bytes[*pos];
+            *pos += 1;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        value
+    }
+
+    fn fields(bytes: &[u8]) -> Vec<(u64, Field<'_>)> {
+        let mut pos = 0;
+        let mut fields = Vec::new();
+        while pos < bytes.len() {
+            let key = varint(bytes, &mut pos);
+            let field = match key & 7 {
+                0 => Field::Varint(varint(bytes, &mut pos)),
+                2 => {
+                    let len = varint(bytes, &mut pos) as usize;
+                    pos += len;
+                    Field::Bytes(&bytes[pos - len..pos])
+                }
+                wire_type => panic!("unexpected wire type {} of field {}", wire_type, key >> 3),
+            };
+            fields.push((key >> 3, field));
+        }
+        fields
+    }
+
+    fn get<'a>(fields: &[(u64, Field<'a>)], number: u64) -> Vec<&'a [u8]> {
+        fields.iter().filter(|(n, _)| *n == number).map(|(_, f)| match f {
+            Field::Bytes(bytes) => *bytes,
+            Field::Varint(_) => panic!("field {} is a varint", number),
+        }).collect()
+    }
+
+    fn get_varint(fields: &[(u64, Field)], number: u64) -> Option<u64> {
+        fields.iter().find(|(n, _)| *n == number).map(|(_, f)| match f {
+            Field::Varint(value) => *value,
+            Field::Bytes(_) => panic!("field {} is length delimited", number),
+        })
+    }
+
+    fn string(bytes: &[u8]) -> String {
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    // ValueInfoProto: name = 1, type = 2 > tensor_type = 1 > (elem_type = 1, shape = 2 > dim = 1)
+    fn value_info(bytes: &[u8]) -> (String, u64, Vec<String>) {
+        let info = fields(bytes);
+        let tensor_type = fields(get(&fields(get(&info, 2)[0]), 1)[0]);
+        let dims = get(&fields(get(&tensor_type, 2)[0]), 1).into_iter().map(|dim| {
+            let dim = fields(dim);
+            // dim_value = 1, dim_param = 2
+            match get_varint(&dim, 1) {
+                Some(value) => value.to_string(),
+                None => string(get(&dim, 2)[0]),
+            }
+        }).collect();
+        (string(get(&info, 1)[0]), get_varint(&tensor_type, 1).unwrap(), dims)
+    }
+
+    #[test]
+    fn onnx_wire_format_follows_the_spec() {
+        let mlp = MLP::new(&[4, 6, 3], Activation::Tanh, &mut StdRng::seed_from_u64(0));
+        let bytes = mlp_to_onnx(&mlp).encode_to_vec();
+
+        // ModelProto: ir_version = 1, graph = 7, opset_import = 8 > (domain = 1, version = 2)
+        let model = fields(&bytes);
+        assert_eq!(get_varint(&model, 1), Some(8));
+        let opsets = get(&model, 8);
+        assert_eq!(opsets.len(), 1);
+        let opset = fields(opsets[0]);
+        // the default domain, `ai.onnx`, is the empty string and may be left out
+        assert!(get(&opset, 1).iter().all(|domain| domain.is_empty()));
+        assert_eq!(get_varint(&opset, 2), Some(13));
+
+        // GraphProto: node = 1, initializer = 5, input = 11, output = 12
+        let graph = fields(get(&model, 7)[0]);
+        let inputs: Vec<_> = get(&graph, 11).into_iter().map(value_info).collect();
+        let outputs: Vec<_> = get(&graph, 12).into_iter().map(value_info).collect();
+        // TensorProto.DataType.FLOAT = 1
+        assert_eq!(inputs, vec![("input".to_string(), 1, vec!["batch".to_string(), "4".to_string()])]);
+        assert_eq!(outputs, vec![("logits".to_string(), 1, vec!["batch".to_string(), "3".to_string()])]);
+
+        // TensorProto: dims = 1 (packed), data_type = 2, float_data = 4 (packed), name = 8
+        let mut initializers = Vec::new();
+        for tensor in get(&graph, 5) {
+            let tensor = fields(tensor);
+            let packed = get(&tensor, 1)[0];
+            let mut pos = 0;
+            let mut dims = Vec::new();
+            while pos < packed.len() {
+                dims.push(varint(packed, &mut pos));
+            }
+            assert_eq!(get_varint(&tensor, 2), Some(1));
+            assert_eq!(get(&tensor, 4)[0].len() as u64, 4 * dims.iter().product::<u64>());
+            initializers.push((string(get(&tensor, 8)[0]), dims));
+        }
+        let expected: Vec<(String, Vec<u64>)> = vec![
+            ("W0".to_string(), vec![4, 6]), ("b0".to_string(), vec![6]),
+            ("W1".to_string(), vec![6, 3]), ("b1".to_string(), vec![3]),
+        ];
+        assert_eq!(initializers, expected);
+
+        // NodeProto: input = 1, output = 2, op_type = 4. Nodes must be sorted topologically,
+        // every input is the graph input, an initializer or the output of an earlier node
+        let mut known: Vec<String> = std::iter::once("input".to_string())
+            .chain(initializers.into_iter().map(|(name, _)| name))
+            .collect();
+        let mut op_types = Vec::new();
+        for node in get(&graph, 1) {
+            let node = fields(node);
+            for input in get(&node, 1) {
+                assert!(known.contains(&string(input)), "{} isn't computed yet", string(input));
+            }
+            known.extend(get(&node, 2).into_iter().map(string));
+            op_types.push(string(get(&node, 4)[0]));
+        }
+        assert_eq!(op_types, vec!["MatMul", "Add", "Tanh", "MatMul", "Add"]);
+        assert_eq!(known.last().map(String::as_str), Some("logits"));
+    }
+
+    #[test]
+    fn onnx_round_trip() {
+        let mlp = MLP::new(&[4, 6, 5, 3], Activation::ReLU, &mut StdRng::seed_from_u64(0));
+        let bytes = mlp_to_onnx(&mlp).encode_to_vec();
+        let decoded = onnx::ModelProto::decode(bytes.as_slice()).unwrap();
+        let graph = decoded.graph.as_ref().unwrap();
+        assert_eq!(graph.initializer.len(), 6);
+        // MatMul, Add and Relu for the hidden layers, no activation after the last one
+        assert_eq!(graph.node.len(), 3 + 3 + 2);
+
+        let x = [0.3, -1.0, 2.5, 0.1];
+        let expected = mlp.predict(&x);
+        let outputs = onnx_predict(&decoded, &x).unwrap();
+        for (a, b) in expected.iter().zip(outputs.iter()) {
+            assert!((a - b).abs() < 1e-5, "{:?} != {:?}", outputs, expected);
+        }
+    }
+}
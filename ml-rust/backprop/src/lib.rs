@@ -0,0 +1,688 @@
+
+pub mod checkpoint;
+pub mod conv;
+pub mod datasets;
+pub mod diagnostics;
+pub mod display;
+pub mod export;
+pub mod nn;
+pub mod optim;
+pub mod parser;
+pub mod plot;
+pub mod recurrent;
+pub mod regularization;
+pub mod schedule;
+pub mod serialize;
+pub mod symbolic;
+pub mod train;
+#[cfg(test)]
+mod test_util;
+
+use std::ops::{Add, Mul, Sub, Div};
+use std::fs::File;
+use std::io::*;
+use num_traits::Pow;
+use std::sync::atomic::{AtomicU64, Ordering};
+use serde::{Deserialize, Serialize};
+use graphviz_rust::{
+    cmd::Format,
+    exec, parse,
+    printer::PrinterContext,
+};
+use uuid::Uuid;
+
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[allow(clippy::enum_variant_names)]
+pub enum Op {
+    Add,
+    Mult,
+    Sub,
+    Div,
+    Pow,
+    Tanh,
+    Sigmoid,
+    ReLU,
+    Exp,
+    Log,
+    Abs,
+    // maximum of any number of children, used by max pooling
+    Max,
+    // segment of the graph rebuilt during backward, see `Value::checkpoint`
+    Checkpoint,
+    // NoOp for leaf (input) nodes that are not composed from other functions
+    #[default]
+    NoOp
+}
+
+impl Op {
+    /// Backward function matching the operation, used to rebuild a graph
+    /// whose function pointers were not serialized
+    fn backward_fn(&self) -> Option<fn(&mut Value)> {
+        match self {
+            Op::Add => Some(Value::backward_add),
+            Op::Mult => Some(Value::backward_mult),
+            Op::Sub => Some(Value::backward_sub),
+            Op::Div => Some(Value::backward_div),
+            Op::Pow => Some(Value::backward_pow),
+            Op::Tanh => Some(Value::backward_tanh),
+            Op::Sigmoid => Some(Value::backward_sigmoid),
+            Op::ReLU => Some(Value::backward_relu),
+            Op::Exp => Some(Value::backward_exp),
+            Op::Log => Some(Value::backward_log),
+            Op::Abs => Some(Value::backward_abs),
+            Op::Max => Some(Value::backward_max),
+            Op::Checkpoint => Some(Value::backward_checkpoint),
+            _ => None
+        }
+    }
+}
+
+// ids only have to be unique within the process. They depend on everything built before and on
+// the interleaving of threads building graphs in parallel, so the exports don't write them as is:
+// `to_dot` names nodes by label and `serialize` numbers the ids of each graph from 1
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Next id of the counter, the nil id is left to `Value::default()`
+pub fn next_id() -> Uuid {
+    Uuid::from_u128(NEXT_ID.fetch_add(1, Ordering::Relaxed) as u128)
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct Value {
+    _id: Uuid,
+    data: f64,
+    children: Vec<Self>, // children of each value, e.g. a = b + c, b and c are children of a
+    op: Op,
+    grad: f64,
+    // function pointers can't be serialized, they are restored from `op` on load
+    #[serde(skip)]
+    backward: Option<fn(&mut Value)>,
+    // only set on checkpoints, closures can't be serialized either
+    #[serde(skip)]
+    recompute: Option<checkpoint::Recompute>,
+    label: String 
+} 
+
+
+impl Value {
+
+    pub fn id(&self) -> Uuid {
+        self._id
+    }
+
+    pub fn data(&self) -> f64 {
+        self.data
+    }
+
+    pub fn grad(&self) -> f64 {
+        self.grad
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn op(&self) -> &Op {
+        &self.op
+    }
+
+    pub fn children(&self) -> &[Value] {
+        &self.children
+    }
+
+    pub fn set_children(&mut self, children: Vec<Value>){
+        self.children = children;
+    }
+
+    pub fn set_id(&mut self, id: Uuid){
+        self._id = id;
+    }
+
+    pub fn set_op(&mut self, op: Op){
+        self.op = op
+    }
+
+    pub fn set_label(&mut self, label: &str){
+        self.label = label.to_string();
+    }
+    
+    pub fn set_gradient(&mut self, grad: f64){
+        self.grad = grad
+    }
+
+    pub fn set_data(&mut self, data: f64){
+        self.data = data
+    }
+
+    pub fn set_backward(&mut self, backward: Option<fn(&mut Value)>) {
+        self.backward = backward;
+    }
+
+    /// Leaf with a new id, e.g. an input or a parameter
+    pub fn leaf(label: &str, data: f64) -> Value {
+        let mut value = Value::default();
+        value.set_id(next_id());
+        value.set_data(data);
+        value.set_label(label);
+        value
+    }
+
+    /// Leaf holding a constant operand, e.g. the 2 in `a * 2`, labelled with its value
+    pub fn scalar(data: f64) -> Value {
+        Value::leaf(&format!("scalar_{}", data), data)
+    }
+
+    /// Whether the value is a constant operand created by `scalar`
+    pub fn is_constant(&self) -> bool {
+        self.children.is_empty() && self.label.starts_with("scalar_")
+    }
+
+    /// Leaf with the same data and label but a new id, gradients don't flow past it
+    pub fn detach(&self) -> Value {
+        Value::leaf(&self.label, self.data)
+    }
+
+    fn _backward(&mut self) {
+        if let Some(f) = self.backward {
+            f(self);
+        }
+    }
+
+    fn backward_add(v: &mut Value) {
+        if v.children.len() != 2 {
+            return; // Safety check
+        }
+        //let (lhs, rhs) = (&mut v.children[0], &mut v.children[1]);
+        v.children[0].grad += v.grad;
+        v.children[1].grad += v.grad;
+    }
+
+    fn backward_mult(v: &mut Value) {
+        if v.children.len() != 2 {
+            return; // Safety check
+        }
+        //let (lhs, rhs) = (&mut v.children[0], &mut v.children[1]);
+        v.children[0].grad += v.children[1].data * v.grad;
+        v.children[1].grad += v.children[0].data * v.grad;
+    }
+
+    fn backward_sub(v: &mut Value) {
+        if v.children.len() != 2 {
+            return; // Safety check
+        }
+        v.children[0].grad += v.grad;
+        v.children[1].grad -= v.grad;
+    }
+
+    fn backward_div(v: &mut Value) {
+        if v.children.len() != 2 {
+            return; // Safety check
+        }
+        // d(a/b)/da = 1/b, d(a/b)/db = -a/b^2
+        let (lhs, rhs) = (v.children[0].data, v.children[1].data);
+        v.children[0].grad += v.grad / rhs;
+        v.children[1].grad -= lhs / (rhs * rhs) * v.grad;
+    }
+
+    fn backward_pow(v: &mut Value){
+        if v.children.len() != 2 {
+            return; // Safety check
+        }
+        // x^n = nx^n-1
+        v.children[0].grad += v.children[1].data * (v.children[0].data.pow(v.children[1].data - 1.)) * v.grad
+        // second children is a power, gradient doesn't flow back
+    }
+
+    fn backward_tanh(v: &mut Value) {
+        if v.children.len() != 1 {
+            return; // Safety check
+        }
+        // tanh'(x) = 1 - tanh(x)^2
+        v.children[0].grad += (1. - v.data * v.data) * v.grad;
+    }
+
+    fn backward_sigmoid(v: &mut Value) {
+        if v.children.len() != 1 {
+            return; // Safety check
+        }
+        // sigmoid'(x) = sigmoid(x) (1 - sigmoid(x))
+        v.children[0].grad += v.data * (1. - v.data) * v.grad;
+    }
+
+    fn backward_relu(v: &mut Value) {
+        if v.children.len() != 1 {
+            return; // Safety check
+        }
+        if v.children[0].data > 0. {
+            v.children[0].grad += v.grad;
+        }
+    }
+
+    fn backward_exp(v: &mut Value) {
+        if v.children.len() != 1 {
+            return; // Safety check
+        }
+        v.children[0].grad += v.data * v.grad;
+    }
+
+    fn backward_log(v: &mut Value) {
+        if v.children.len() != 1 {
+            return; // Safety check
+        }
+        v.children[0].grad += v.grad / v.children[0].data;
+    }
+
+    fn backward_abs(v: &mut Value) {
+        if v.children.len() != 1 {
+            return; // Safety check
+        }
+        // |x|' = sign(x), taken as 0 at 0
+        let x = v.children[0].data;
+        if x != 0. {
+            v.children[0].grad += x.signum() * v.grad;
+        }
+    }
+
+    fn backward_max(v: &mut Value) {
+        // the gradient only flows to the (first) largest child
+        let argmax = v.children.iter()
+            .enumerate()
+            .fold(None, |best: Option<(usize, f64)>, (i, c)| match best {
+                Some((_, data)) if data >= c.data => best,
+                _ => Some((i, c.data)),
+            });
+        if let Some((i, _)) = argmax {
+            v.children[i].grad += v.grad;
+        }
+    }
+
+    fn unary(self, data: f64, op: Op, backward: fn(&mut Value)) -> Value {
+        let mut out = Value::default();
+        out.set_id(next_id());
+        out.set_backward(Some(backward));
+        out.set_data(data);
+        out.set_op(op);
+        out.set_children(vec![self]);
+
+        out
+    }
+
+    pub fn tanh(self) -> Value {
+        let data = self.data.tanh();
+        self.unary(data, Op::Tanh, Self::backward_tanh)
+    }
+
+    /// Logistic function `1 / (1 + exp(-x))`, used by the gates of the recurrent cells
+    pub fn sigmoid(self) -> Value {
+        let data = 1. / (1. + (-self.data).exp());
+        self.unary(data, Op::Sigmoid, Self::backward_sigmoid)
+    }
+
+    pub fn relu(self) -> Value {
+        let data = self.data.max(0.);
+        self.unary(data, Op::ReLU, Self::backward_relu)
+    }
+
+    pub fn exp(self) -> Value {
+        let data = self.data.exp();
+        self.unary(data, Op::Exp, Self::backward_exp)
+    }
+
+    /// Natural logarithm
+    pub fn ln(self) -> Value {
+        let data = self.data.ln();
+        self.unary(data, Op::Log, Self::backward_log)
+    }
+
+    pub fn abs(self) -> Value {
+        let data = self.data.abs();
+        self.unary(data, Op::Abs, Self::backward_abs)
+    }
+
+    /// Largest of `values`, which must not be empty
+    pub fn max(values: Vec<Value>) -> Value {
+        let data = values.iter().map(|v| v.data).fold(f64::NEG_INFINITY, f64::max);
+        let mut out = Value::default();
+        out.set_id(next_id());
+        out.set_backward(Some(Self::backward_max));
+        out.set_data(data);
+        out.set_op(Op::Max);
+        out.set_children(values);
+
+        out
+    }
+
+    /// Sum of `values` as a chain of additions, a constant 0 when there are none
+    pub fn sum(values: impl IntoIterator<Item = Value>) -> Value {
+        values.into_iter().reduce(|acc, v| acc + v).unwrap_or_else(|| Value::scalar(0.))
+    }
+
+    pub fn backward(&mut self) {
+        self.set_gradient(1.0);
+
+        // every node is owned by exactly one parent, so once the parent has pushed its
+        // gradient down the node's gradient is complete and it can be propagated further
+        let mut stack: Vec<&mut Value> = vec![self];
+        while let Some(node) = stack.pop() {
+            node._backward();
+            stack.extend(node.children.iter_mut());
+        }
+    }
+
+}
+
+impl Add<Self> for Value {
+    type Output = Value;
+
+
+    fn add(self, rhs: Self) -> Self::Output {
+
+        let mut out = Value::default();
+        out.set_id(next_id());
+        out.set_backward(Some(Self::backward_add));
+        out.set_data(self.data + rhs.data);
+        out.set_op(Op::Add);
+        out.set_children(vec![self, rhs]);
+        
+        out
+    }
+}
+
+impl<T> Add<T> for Value 
+where 
+    T: Into<f64> + Copy
+    {
+    type Output = Value;
+
+
+    fn add(self, rhs: T) -> Self::Output {
+        let rhs_val = Value::scalar(rhs.into());
+
+        let mut out = Value::default();
+        out.set_id(next_id());
+        out.set_backward(Some(Self::backward_add));
+        out.set_data(self.data + rhs_val.data);
+        out.set_op(Op::Add);
+        out.set_children(vec![self, rhs_val]);
+        
+        out
+    }
+}
+
+
+impl Mul for Value {
+    type Output = Value;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        let mut out = Value::default();
+        out.set_id(next_id());
+        out.set_backward(Some(Self::backward_mult));
+        out.set_data(self.data * rhs.data);
+        out.set_op(Op::Mult);
+        out.set_children(vec![self, rhs]);
+        
+        out
+    }
+}
+
+impl<T> Mul<T> for Value 
+where 
+    T: Into<f64> + Copy
+    {
+    type Output = Value;
+
+
+    fn mul(self, rhs: T) -> Self::Output {
+        let rhs_val = Value::scalar(rhs.into());
+
+        let mut out = Value::default();
+        out.set_id(next_id());
+        out.set_backward(Some(Self::backward_mult));
+        out.set_data(self.data * rhs_val.data);
+        out.set_op(Op::Mult);
+        out.set_children(vec![self, rhs_val]);
+        
+        out
+    }
+}
+
+
+impl Sub for Value {
+    type Output = Value;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        let mut out = Value::default();
+        out.set_id(next_id());
+        out.set_backward(Some(Self::backward_sub));
+        out.set_data(self.data - rhs.data);
+        out.set_op(Op::Sub);
+        out.set_children(vec![self, rhs]);
+
+        out
+    }
+}
+
+impl<T> Sub<T> for Value
+where
+    T: Into<f64> + Copy
+    {
+    type Output = Value;
+
+    fn sub(self, rhs: T) -> Self::Output {
+        self - Value::scalar(rhs.into())
+    }
+}
+
+
+impl Div for Value {
+    type Output = Value;
+
+    fn div(self, rhs: Self) -> Self::Output {
+        let mut out = Value::default();
+        out.set_id(next_id());
+        out.set_backward(Some(Self::backward_div));
+        out.set_data(self.data / rhs.data);
+        out.set_op(Op::Div);
+        out.set_children(vec![self, rhs]);
+
+        out
+    }
+}
+
+impl<T> Div<T> for Value
+where
+    T: Into<f64> + Copy
+    {
+    type Output = Value;
+
+    fn div(self, rhs: T) -> Self::Output {
+        self / Value::scalar(rhs.into())
+    }
+}
+
+
+impl<T> Pow<T> for Value 
+    where 
+    T: Into<f64> + Copy
+{
+    type Output = Value;
+
+    fn pow(self, rhs: T) -> Self::Output {
+        let rhs_val = Value::scalar(rhs.into());
+
+        let mut out = Value::default();
+        out.set_id(next_id());
+        out.set_backward(Some(Self::backward_pow));
+        out.set_data(self.data.pow(rhs_val.data));
+        out.set_op(Op::Pow);
+        out.set_children(vec![self, rhs_val]);
+        
+        out
+    }
+}
+
+fn save_svg_to_file(svg_data: &[u8], file_path: &str) -> Result<()> {
+    // Create or truncate the file
+    let mut file = File::create(file_path)?;
+    // Write the SVG data to the file
+    file.write_all(svg_data)?;
+    Ok(())
+}
+
+fn build_graphviz_op_node(id: &str, label: &str) -> String{
+    format!("{}[label=\"{}\"]\n", id, label)
+}
+
+fn build_graphviz_data_node(id: &str, label: &str, data: f64, grad: f64) -> String{
+    format!("{}[shape={}, label=\"{} | data {} | grad {}\"]\n", id, "square", label, data, grad)
+}
+
+// copies of a parameter share their label and are drawn as one node, constants and
+// unlabelled nodes are told apart by the op node they feed, so the ids don't depend on
+// anything but the shape of the graph
+fn graphviz_id(value: &Value, op_node: &str, index: usize) -> String {
+    if value.label.is_empty() || value.is_constant() {
+        format!("\"{}@{}.{}\"", value.label, op_node, index)
+    } else {
+        format!("\"{}\"", value.label)
+    }
+}
+
+fn build_computational_graph(root: &Value, root_id: &str, current_op_n: &mut usize) -> String{
+
+    let mut graphviz_str: String = String::new();
+    if root.children.is_empty() {
+        return graphviz_str
+    }
+    let id_op_node = format!("op{}", current_op_n);
+    *current_op_n += 1;
+    match root.op {
+        Op::Mult => {
+            graphviz_str.push_str(&build_graphviz_op_node(id_op_node.as_str(), "*"));
+        },
+        Op::Add => {
+            graphviz_str.push_str(&build_graphviz_op_node(id_op_node.as_str(), "+"));
+        },
+        Op::Sub => {
+            graphviz_str.push_str(&build_graphviz_op_node(id_op_node.as_str(), "-"));
+        },
+        Op::Div => {
+            graphviz_str.push_str(&build_graphviz_op_node(id_op_node.as_str(), "/"));
+        },
+        Op::Pow => {
+            graphviz_str.push_str(&build_graphviz_op_node(id_op_node.as_str(), "**"))
+        }
+        Op::Tanh => {
+            graphviz_str.push_str(&build_graphviz_op_node(id_op_node.as_str(), "tanh"))
+        }
+        Op::Sigmoid => {
+            graphviz_str.push_str(&build_graphviz_op_node(id_op_node.as_str(), "sigmoid"))
+        }
+        Op::ReLU => {
+            graphviz_str.push_str(&build_graphviz_op_node(id_op_node.as_str(), "relu"))
+        }
+        Op::Exp => {
+            graphviz_str.push_str(&build_graphviz_op_node(id_op_node.as_str(), "exp"))
+        }
+        Op::Log => {
+            graphviz_str.push_str(&build_graphviz_op_node(id_op_node.as_str(), "log"))
+        }
+        Op::Abs => {
+            graphviz_str.push_str(&build_graphviz_op_node(id_op_node.as_str(), "abs"))
+        }
+        Op::Max => {
+            graphviz_str.push_str(&build_graphviz_op_node(id_op_node.as_str(), "max"))
+        }
+        Op::Checkpoint => {
+            graphviz_str.push_str(&build_graphviz_op_node(id_op_node.as_str(), "checkpoint"))
+        }
+        _ => {}
+    }
+
+    graphviz_str.push_str(&format!("{} -> {}\n", id_op_node, root_id));
+
+    for (i, child) in root.children.iter().enumerate(){
+        let child_id = graphviz_id(child, &id_op_node, i);
+        graphviz_str.push_str(&build_graphviz_data_node(&child_id, &child.label, child.data, child.grad));
+        graphviz_str.push_str(&format!("{} -> {}\n", child_id, id_op_node));
+        graphviz_str.push_str(&build_computational_graph(child, &child_id, current_op_n));
+    }
+
+    graphviz_str
+
+    
+}
+
+/// DOT source of the graph drawn by `draw_comp`
+pub fn to_dot(value: &Value) -> String {
+    let root_id = graphviz_id(value, "root", 0);
+    let graph_str = format!(r#" strict digraph Comp {{
+        {}
+    "#, build_graphviz_data_node(&root_id, value.label.as_str(), value.data, value.grad));
+
+    format!("{} {}}}", graph_str, build_computational_graph(value, &root_id, &mut 0))
+}
+
+
+pub fn draw_comp(value: &Value){
+    let final_str = to_dot(value);
+    println!("{}", final_str);
+    // let g: Graph = parse(
+    //     &final_str).unwrap();
+    let g = parse(&final_str).unwrap();
+
+    let graph_svg = exec(
+    g,
+    &mut PrinterContext::default(),
+    vec![Format::Svg.into()],
+    )
+    .unwrap();
+
+    let _ = save_svg_to_file(&graph_svg, "comp_graph.svg");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // L = (2 x^3)^2, nested so that the gradient has to go through two Pow nodes
+    fn pow_graph(x: f64) -> Value {
+        (Value::leaf("x", x).pow(3) * 2).pow(2)
+    }
+
+    #[test]
+    fn pow_gradient_matches_finite_difference() {
+        let (x, h) = (1.3, 1e-6);
+        let mut l = pow_graph(x);
+        l.backward();
+        // the leaf is the first child all the way down
+        let mut node = &l;
+        while !node.children.is_empty() {
+            node = &node.children[0];
+        }
+        assert_eq!(node.data, x);
+        let numeric = (pow_graph(x + h).data - pow_graph(x - h).data) / (2. * h);
+        assert!((node.grad - numeric).abs() < 1e-4 * numeric.abs(), "{} vs {}", node.grad, numeric);
+    }
+
+    fn example_graph() -> Value {
+        let a = Value::leaf("a", 2.0);
+        let mut c = a.clone() + Value::leaf("b", -3.0);
+        c.set_label("c");
+        // the unlabelled product and the two constants need ids of their own
+        let mut l = (c * a * 2).pow(2) + 2;
+        l.set_label("L");
+        l
+    }
+
+    #[test]
+    fn dot_output_is_reproducible() {
+        let (first, second) = (example_graph(), example_graph());
+        assert_ne!(first.id(), second.id());
+        let dot = to_dot(&first);
+        assert_eq!(dot, to_dot(&second));
+        assert!(dot.contains("\"scalar_2@op0.1\""), "{}", dot);
+        assert!(parse(&dot).is_ok(), "{}", dot);
+    }
+}
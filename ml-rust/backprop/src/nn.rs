@@ -0,0 +1,270 @@
+use std::error::Error;
+use std::sync::Arc;
+
+use rand::Rng;
+
+use super::serialize::{ParameterMismatch, ParameterSet};
+use super::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Activation {
+    Tanh,
+    ReLU,
+    // gates of the recurrent cells
+    Sigmoid,
+    // no non linearity, used for the output layer producing logits
+    Linear,
+}
+
+impl Activation {
+    pub fn apply(&self, value: Value) -> Value {
+        match self {
+            Activation::Tanh => value.tanh(),
+            Activation::ReLU => value.relu(),
+            Activation::Sigmoid => value.sigmoid(),
+            Activation::Linear => value,
+        }
+    }
+}
+
+/// Leaves holding the features of a sample, labelled `x0`, `x1`, ...
+pub fn inputs(features: &[f64]) -> Vec<Value> {
+    features.iter().enumerate().map(|(i, &x)| Value::leaf(&format!("x{}", i), x)).collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct Neuron {
+    weights: Vec<Value>,
+    bias: Value,
+    activation: Activation,
+}
+
+impl Neuron {
+    /// Weights are drawn uniformly in `[-1/sqrt(n_inputs), 1/sqrt(n_inputs)]`, the bias starts at zero
+    pub fn new(n_inputs: usize, activation: Activation, name: &str, rng: &mut impl Rng) -> Neuron {
+        let bound = 1. / (n_inputs as f64).sqrt();
+        Neuron {
+            weights: (0..n_inputs)
+                .map(|i| Value::leaf(&format!("w{}_{}", name, i), rng.random_range(-bound..bound)))
+                .collect(),
+            bias: Value::leaf(&format!("b{}", name), 0.),
+            activation,
+        }
+    }
+
+    /// Weights and bias at zero
+    pub fn zeros(n_inputs: usize, activation: Activation, name: &str) -> Neuron {
+        Neuron {
+            weights: (0..n_inputs).map(|i| Value::leaf(&format!("w{}_{}", name, i), 0.)).collect(),
+            bias: Value::leaf(&format!("b{}", name), 0.),
+            activation,
+        }
+    }
+
+    /// `activation(w . x + b)`, the parameters are copied into the returned graph
+    pub fn forward(&self, x: &[Value]) -> Value {
+        let pre_activation = self.weights.iter().zip(x.iter())
+            .fold(self.bias.clone(), |acc, (w, xi)| acc + w.clone() * xi.clone());
+        self.activation.apply(pre_activation)
+    }
+
+    pub fn weights(&self) -> &[Value] {
+        &self.weights
+    }
+
+    pub fn bias(&self) -> &Value {
+        &self.bias
+    }
+
+    pub fn activation(&self) -> Activation {
+        self.activation
+    }
+
+    pub fn parameters(&self) -> Vec<&Value> {
+        self.weights.iter().chain(std::iter::once(&self.bias)).collect()
+    }
+
+    pub fn parameters_mut(&mut self) -> Vec<&mut Value> {
+        self.weights.iter_mut().chain(std::iter::once(&mut self.bias)).collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Layer {
+    neurons: Vec<Neuron>,
+}
+
+impl Layer {
+    pub fn new(n_inputs: usize, n_outputs: usize, activation: Activation, index: usize, rng: &mut impl Rng) -> Layer {
+        Layer {
+            neurons: (0..n_outputs)
+                .map(|j| Neuron::new(n_inputs, activation, &format!("{}_{}", index, j), rng))
+                .collect(),
+        }
+    }
+
+    pub fn zeros(n_inputs: usize, n_outputs: usize, activation: Activation, index: usize) -> Layer {
+        Layer {
+            neurons: (0..n_outputs)
+                .map(|j| Neuron::zeros(n_inputs, activation, &format!("{}_{}", index, j)))
+                .collect(),
+        }
+    }
+
+    pub fn forward(&self, x: &[Value]) -> Vec<Value> {
+        self.neurons.iter().map(|n| n.forward(x)).collect()
+    }
+
+    pub fn neurons(&self) -> &[Neuron] {
+        &self.neurons
+    }
+
+    pub fn parameters(&self) -> Vec<&Value> {
+        self.neurons.iter().flat_map(|n| n.parameters()).collect()
+    }
+
+    pub fn parameters_mut(&mut self) -> Vec<&mut Value> {
+        self.neurons.iter_mut().flat_map(|n| n.parameters_mut()).collect()
+    }
+}
+
+/// A network trained by `train::fit`, mapping the features of a sample to output logits
+pub trait Model: Sync {
+    fn forward_sample(&self, features: &[f64]) -> Vec<Value>;
+
+    fn parameters(&self) -> Vec<&Value>;
+
+    fn parameters_mut(&mut self) -> Vec<&mut Value>;
+
+    /// Output values for a sample, without keeping the graph around
+    fn predict(&self, features: &[f64]) -> Vec<f64> {
+        self.forward_sample(features).iter().map(|v| v.data()).collect()
+    }
+}
+
+/// Multi layer perceptron, hidden layers use `hidden_activation` and the last one is linear
+#[derive(Debug, Clone)]
+pub struct MLP {
+    layer_sizes: Vec<usize>,
+    layers: Vec<Layer>,
+}
+
+impl MLP {
+    fn build(
+        layer_sizes: &[usize],
+        hidden_activation: Activation,
+        mut layer: impl FnMut(usize, usize, Activation, usize) -> Layer,
+    ) -> MLP {
+        let n_layers = layer_sizes.len() - 1;
+        let layers = (0..n_layers)
+            .map(|i| {
+                let activation = if i + 1 == n_layers { Activation::Linear } else { hidden_activation };
+                layer(layer_sizes[i], layer_sizes[i + 1], activation, i)
+            })
+            .collect();
+        MLP { layer_sizes: layer_sizes.to_vec(), layers }
+    }
+
+    /// `layer_sizes` starts with the number of inputs, e.g. `[4, 8, 3]` for Iris
+    pub fn new(layer_sizes: &[usize], hidden_activation: Activation, rng: &mut impl Rng) -> MLP {
+        MLP::build(layer_sizes, hidden_activation, |n_in, n_out, activation, i| {
+            Layer::new(n_in, n_out, activation, i, rng)
+        })
+    }
+
+    /// Every parameter at zero. Only suited to convex models such as `[n_features, n_classes]`
+    /// (softmax regression), the hidden units of a deeper network would stay identical
+    pub fn zeros(layer_sizes: &[usize], hidden_activation: Activation) -> MLP {
+        MLP::build(layer_sizes, hidden_activation, Layer::zeros)
+    }
+
+    pub fn forward(&self, x: &[Value]) -> Vec<Value> {
+        self.layers.iter().fold(x.to_vec(), |activations, layer| layer.forward(&activations))
+    }
+
+    /// Same outputs as `forward`, with every block of `layers_per_segment` layers checkpointed:
+    /// only the outputs of each block are kept, the layers inside are run again during backward
+    pub fn forward_checkpointed(&self, x: &[Value], layers_per_segment: usize) -> Vec<Value> {
+        self.layers.chunks(layers_per_segment.max(1)).fold(x.to_vec(), |activations, block| {
+            let block = Arc::new(block.to_vec());
+            let n_outputs = block.last().map_or(0, |l| l.neurons().len());
+            Value::checkpoint_many(&activations, n_outputs, move |x| {
+                block.iter().fold(x.to_vec(), |activations, layer| layer.forward(&activations))
+            })
+        })
+    }
+
+    /// Output values of the network for a sample, without keeping the graph around
+    pub fn predict(&self, features: &[f64]) -> Vec<f64> {
+        self.forward(&inputs(features)).iter().map(|v| v.data()).collect()
+    }
+
+    pub fn layer_sizes(&self) -> &[usize] {
+        &self.layer_sizes
+    }
+
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    pub fn parameters(&self) -> Vec<&Value> {
+        self.layers.iter().flat_map(|l| l.parameters()).collect()
+    }
+
+    pub fn parameters_mut(&mut self) -> Vec<&mut Value> {
+        self.layers.iter_mut().flat_map(|l| l.parameters_mut()).collect()
+    }
+
+    pub fn parameter_set(&self) -> ParameterSet {
+        let params: Vec<Value> = self.parameters().into_iter().cloned().collect();
+        ParameterSet::from_values(&self.layer_sizes, &params)
+    }
+
+    /// Restores parameters saved with `parameter_set`, possibly by another run, matching them by
+    /// label. Fails if the layer sizes differ or a parameter is missing
+    pub fn load_parameter_set(&mut self, params: &ParameterSet) -> Result<(), Box<dyn Error>> {
+        if params.layer_sizes != self.layer_sizes {
+            return Err(Box::new(ParameterMismatch {
+                message: format!("layer sizes {:?} stored for a network of {:?}", params.layer_sizes, self.layer_sizes),
+            }));
+        }
+        params.apply_to(&mut self.parameters_mut())
+    }
+}
+
+impl Model for MLP {
+    fn forward_sample(&self, features: &[f64]) -> Vec<Value> {
+        self.forward(&inputs(features))
+    }
+
+    fn parameters(&self) -> Vec<&Value> {
+        MLP::parameters(self)
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut Value> {
+        MLP::parameters_mut(self)
+    }
+}
+
+/// Index of the largest output, i.e. the predicted class
+pub fn argmax(outputs: &[f64]) -> usize {
+    outputs.iter()
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .map(|(i, _)| i)
+        .unwrap_or_default()
+}
+
+/// Cross entropy of the softmax of `logits` against the class `target`.
+/// The largest logit is subtracted first so that `exp` can't overflow
+pub fn softmax_cross_entropy(logits: &[Value], target: usize) -> Value {
+    let max = logits.iter().map(|l| l.data()).fold(f64::NEG_INFINITY, f64::max);
+    let sum_exp = Value::sum(logits.iter().map(|l| (l.clone() - max).exp()));
+    sum_exp.ln() - (logits[target].clone() - max)
+}
+
+/// Mean squared error between `outputs` and `targets`
+pub fn mse(outputs: &[Value], targets: &[f64]) -> Value {
+    let n = outputs.len() as f64;
+    Value::sum(outputs.iter().zip(targets.iter()).map(|(o, &t)| num_traits::Pow::pow(o.clone() - t, 2))) / n
+}
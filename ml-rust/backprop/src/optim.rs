@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+
+use uuid::Uuid;
+
+use super::Value;
+
+/// Gradients of the leaves of a graph, keyed by id. A parameter is copied in the graph
+/// each time it is used, the gradients of all its copies are summed
+pub type Gradients = HashMap<Uuid, f64>;
+
+/// Adds the gradient of every leaf of `root` to `grads`, after `backward` was called
+pub fn accumulate_gradients(root: &Value, grads: &mut Gradients) {
+    if root.children.is_empty() {
+        *grads.entry(root._id).or_insert(0.) += root.grad;
+    }
+    for child in root.children.iter() {
+        accumulate_gradients(child, grads);
+    }
+}
+
+/// Multiplies every gradient by `factor`, e.g. `1 / batch_size` to average a batch
+pub fn scale_gradients(grads: &mut Gradients, factor: f64) {
+    for grad in grads.values_mut() {
+        *grad *= factor;
+    }
+}
+
+/// Bounds applied to the gradients before the optimizer step
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GradientClip {
+    // rescale all gradients together so that their global L2 norm is at most the given value
+    Norm(f64),
+    // clamp each gradient to `[-value, value]`
+    Value(f64),
+}
+
+/// Global L2 norm of the gradients
+pub fn gradient_norm(grads: &Gradients) -> f64 {
+    grads.values().map(|g| g * g).sum::<f64>().sqrt()
+}
+
+/// Rescales the gradients so that their global norm is at most `max_norm`,
+/// returns the norm before clipping
+pub fn clip_grad_norm(grads: &mut Gradients, max_norm: f64) -> f64 {
+    let norm = gradient_norm(grads);
+    if norm > max_norm {
+        scale_gradients(grads, max_norm / (norm + 1e-12));
+    }
+    norm
+}
+
+/// Clamps every gradient to `[-clip, clip]`
+pub fn clip_grad_value(grads: &mut Gradients, clip: f64) {
+    for grad in grads.values_mut() {
+        *grad = grad.clamp(-clip, clip);
+    }
+}
+
+impl GradientClip {
+    pub fn apply(&self, grads: &mut Gradients) {
+        match *self {
+            GradientClip::Norm(max_norm) => {
+                clip_grad_norm(grads, max_norm);
+            }
+            GradientClip::Value(clip) => clip_grad_value(grads, clip),
+        }
+    }
+}
+
+/// Updates parameters in place from their accumulated gradients
+pub trait Optimizer {
+    fn step(&mut self, params: &mut [&mut Value], grads: &Gradients);
+
+    fn learning_rate(&self) -> f64;
+
+    fn set_learning_rate(&mut self, lr: f64);
+}
+
+/// Stochastic gradient descent with optional momentum
+#[derive(Debug, Clone)]
+pub struct SGD {
+    lr: f64,
+    momentum: f64,
+    velocity: HashMap<Uuid, f64>,
+}
+
+impl SGD {
+    pub fn new(lr: f64) -> SGD {
+        SGD { lr, momentum: 0., velocity: HashMap::new() }
+    }
+
+    pub fn momentum(mut self, momentum: f64) -> SGD {
+        self.momentum = momentum;
+        self
+    }
+}
+
+impl Optimizer for SGD {
+    fn step(&mut self, params: &mut [&mut Value], grads: &Gradients) {
+        for param in params.iter_mut() {
+            let grad = grads.get(&param._id).copied().unwrap_or_default();
+            let velocity = self.velocity.entry(param._id).or_insert(0.);
+            *velocity = self.momentum * *velocity + grad;
+            param.data -= self.lr * *velocity;
+        }
+    }
+
+    fn learning_rate(&self) -> f64 {
+        self.lr
+    }
+
+    fn set_learning_rate(&mut self, lr: f64) {
+        self.lr = lr;
+    }
+}
+
+/// Adam (Kingma & Ba, 2015)
+#[derive(Debug, Clone)]
+pub struct Adam {
+    lr: f64,
+    beta1: f64,
+    beta2: f64,
+    eps: f64,
+    t: i32,
+    // first and second moment estimates of each parameter
+    moments: HashMap<Uuid, (f64, f64)>,
+}
+
+impl Adam {
+    pub fn new(lr: f64) -> Adam {
+        Adam { lr, beta1: 0.9, beta2: 0.999, eps: 1e-8, t: 0, moments: HashMap::new() }
+    }
+
+    pub fn betas(mut self, beta1: f64, beta2: f64) -> Adam {
+        self.beta1 = beta1;
+        self.beta2 = beta2;
+        self
+    }
+}
+
+impl Optimizer for Adam {
+    fn step(&mut self, params: &mut [&mut Value], grads: &Gradients) {
+        self.t += 1;
+        let bias_correction1 = 1. - self.beta1.powi(self.t);
+        let bias_correction2 = 1. - self.beta2.powi(self.t);
+        for param in params.iter_mut() {
+            let grad = grads.get(&param._id).copied().unwrap_or_default();
+            let (m, v) = self.moments.entry(param._id).or_insert((0., 0.));
+            *m = self.beta1 * *m + (1. - self.beta1) * grad;
+            *v = self.beta2 * *v + (1. - self.beta2) * grad * grad;
+            let m_hat = *m / bias_correction1;
+            let v_hat = *v / bias_correction2;
+            param.data -= self.lr * m_hat / (v_hat.sqrt() + self.eps);
+        }
+    }
+
+    fn learning_rate(&self) -> f64 {
+        self.lr
+    }
+
+    fn set_learning_rate(&mut self, lr: f64) {
+        self.lr = lr;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradients(values: &[f64]) -> (Vec<Uuid>, Gradients) {
+        let ids: Vec<Uuid> = values.iter().map(|_| crate::next_id()).collect();
+        (ids.clone(), ids.into_iter().zip(values.iter().copied()).collect())
+    }
+
+    #[test]
+    fn clipping_by_norm_keeps_the_direction() {
+        let (ids, mut grads) = gradients(&[3., -4.]);
+        assert_eq!(clip_grad_norm(&mut grads, 1.), 5.);
+        let (x, y) = (grads[&ids[0]], grads[&ids[1]]);
+        assert!((x - 0.6).abs() < 1e-9 && (y + 0.8).abs() < 1e-9, "{} {}", x, y);
+        assert!((y / x - -4. / 3.).abs() < 1e-12);
+        // already within the bound, left untouched
+        let before = grads.clone();
+        assert!((clip_grad_norm(&mut grads, 2.) - 1.).abs() < 1e-9);
+        assert_eq!(grads, before);
+    }
+
+    #[test]
+    fn clipping_by_value_clamps_each_gradient() {
+        let (ids, mut grads) = gradients(&[-3., 0.5, 2.]);
+        GradientClip::Value(1.).apply(&mut grads);
+        let clipped: Vec<f64> = ids.iter().map(|id| grads[id]).collect();
+        assert_eq!(clipped, vec![-1., 0.5, 1.]);
+    }
+}
@@ -0,0 +1,410 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use num_traits::Pow;
+
+use super::Value;
+use super::optim::{accumulate_gradients, Gradients};
+
+/// Error raised while parsing an expression, `position` is the byte offset in the source
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    pub position: usize,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(f64),
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Caret,
+    LParen,
+    RParen,
+    Equals,
+    // `;` or a new line, ends a statement
+    Separator,
+}
+
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, ParseError> {
+    let mut tokens = Vec::new();
+    let chars: Vec<(usize, char)> = source.char_indices().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let (pos, c) = chars[i];
+        let token = match c {
+            ' ' | '\t' | '\r' => {
+                i += 1;
+                continue;
+            }
+            '+' => Token::Plus,
+            '-' => Token::Minus,
+            '*' => Token::Star,
+            '/' => Token::Slash,
+            '^' => Token::Caret,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '=' => Token::Equals,
+            ';' | '\n' => Token::Separator,
+            c if c.is_ascii_digit() || c == '.' => {
+                let start = i;
+                while i < chars.len() && (chars[i].1.is_ascii_digit() || chars[i].1 == '.') {
+                    i += 1;
+                }
+                let text: String = chars[start..i].iter().map(|(_, c)| c).collect();
+                let number = text.parse::<f64>().map_err(|_| ParseError {
+                    message: format!("invalid number `{}`", text),
+                    position: pos,
+                })?;
+                tokens.push((Token::Number(number), pos));
+                continue;
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].1.is_alphanumeric() || chars[i].1 == '_') {
+                    i += 1;
+                }
+                let name: String = chars[start..i].iter().map(|(_, c)| c).collect();
+                tokens.push((Token::Ident(name), pos));
+                continue;
+            }
+            c => {
+                return Err(ParseError { message: format!("unexpected character `{}`", c), position: pos });
+            }
+        };
+        tokens.push((token, pos));
+        i += 1;
+    }
+    Ok(tokens)
+}
+
+// functions that can be called in an expression, e.g. `tanh(a * b)`
+fn function(name: &str) -> Option<fn(Value) -> Value> {
+    match name {
+        "tanh" => Some(Value::tanh),
+        "sigmoid" => Some(Value::sigmoid),
+        "relu" => Some(Value::relu),
+        "exp" => Some(Value::exp),
+        "ln" | "log" => Some(Value::ln),
+        "abs" => Some(Value::abs),
+        _ => None,
+    }
+}
+
+struct Parser<'a> {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    end: usize,
+    bindings: &'a HashMap<&'a str, f64>,
+    // one leaf per bound variable, cloned at every use so that all uses share the id
+    leaves: HashMap<String, Value>,
+    // values of earlier statements, referenced by name in later ones
+    named: HashMap<String, Value>,
+    n_intermediate: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(t, _)| t)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens.get(self.pos).map(|(_, p)| *p).unwrap_or(self.end)
+    }
+
+    fn error(&self, message: &str) -> ParseError {
+        ParseError { message: message.to_string(), position: self.position() }
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), ParseError> {
+        if self.peek() == Some(&token) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected {:?}", token)))
+        }
+    }
+
+    fn intermediate(&mut self, mut value: Value) -> Value {
+        value.set_label(&format!("t{}", self.n_intermediate));
+        self.n_intermediate += 1;
+        value
+    }
+
+    // statement := [ident '='] expr
+    fn statement(&mut self) -> Result<Value, ParseError> {
+        let name = match (self.tokens.get(self.pos), self.tokens.get(self.pos + 1)) {
+            (Some((Token::Ident(name), _)), Some((Token::Equals, _))) => Some(name.clone()),
+            _ => None,
+        };
+        if let Some(name) = &name {
+            if self.bindings.contains_key(name.as_str()) || self.named.contains_key(name) {
+                return Err(self.error(&format!("`{}` is already defined", name)));
+            }
+            self.pos += 2;
+        }
+
+        let mut value = self.expr()?;
+        if let Some(name) = name {
+            value.set_label(&name);
+            self.named.insert(name, value.clone());
+        }
+        Ok(value)
+    }
+
+    // expr := term (('+' | '-') term)*
+    fn expr(&mut self) -> Result<Value, ParseError> {
+        let mut lhs = self.term()?;
+        loop {
+            let out = match self.peek() {
+                Some(Token::Plus) => {
+                    self.pos += 1;
+                    lhs + self.term()?
+                }
+                Some(Token::Minus) => {
+                    self.pos += 1;
+                    lhs - self.term()?
+                }
+                _ => return Ok(lhs),
+            };
+            lhs = self.intermediate(out);
+        }
+    }
+
+    // term := unary (('*' | '/') unary)*
+    fn term(&mut self) -> Result<Value, ParseError> {
+        let mut lhs = self.unary()?;
+        loop {
+            let out = match self.peek() {
+                Some(Token::Star) => {
+                    self.pos += 1;
+                    lhs * self.unary()?
+                }
+                Some(Token::Slash) => {
+                    self.pos += 1;
+                    lhs / self.unary()?
+                }
+                _ => return Ok(lhs),
+            };
+            lhs = self.intermediate(out);
+        }
+    }
+
+    // unary := '-' unary | power
+    fn unary(&mut self) -> Result<Value, ParseError> {
+        if self.peek() == Some(&Token::Minus) {
+            self.pos += 1;
+            let operand = self.unary()?;
+            return Ok(self.intermediate(operand * -1.0));
+        }
+        self.power()
+    }
+
+    // power := atom ['^' exponent], exponents are constants as in `Pow<T> for Value`
+    fn power(&mut self) -> Result<Value, ParseError> {
+        let base = self.atom()?;
+        if self.peek() != Some(&Token::Caret) {
+            return Ok(base);
+        }
+        self.pos += 1;
+        let exponent = self.exponent()?;
+        Ok(self.intermediate(base.pow(exponent)))
+    }
+
+    // exponent := ['-'] number ['^' exponent], right associative so `a^2^3` is `a^8`
+    fn exponent(&mut self) -> Result<f64, ParseError> {
+        let sign = if self.peek() == Some(&Token::Minus) {
+            self.pos += 1;
+            -1.0
+        } else {
+            1.0
+        };
+        let base = match self.peek() {
+            Some(Token::Number(n)) => sign * n,
+            _ => return Err(self.error("exponent must be a number")),
+        };
+        self.pos += 1;
+        if self.peek() != Some(&Token::Caret) {
+            return Ok(base);
+        }
+        self.pos += 1;
+        Ok(base.powf(self.exponent()?))
+    }
+
+    // atom := number | ident | function '(' expr ')' | '(' expr ')'
+    fn atom(&mut self) -> Result<Value, ParseError> {
+        match self.peek().cloned() {
+            Some(Token::Number(n)) => {
+                self.pos += 1;
+                Ok(Value::scalar(n))
+            }
+            Some(Token::Ident(name)) if self.tokens.get(self.pos + 1).map(|(t, _)| t) == Some(&Token::LParen) => {
+                let function = function(&name)
+                    .ok_or_else(|| self.error(&format!("unknown function `{}`", name)))?;
+                self.pos += 2;
+                let argument = self.expr()?;
+                self.expect(Token::RParen)?;
+                Ok(self.intermediate(function(argument)))
+            }
+            Some(Token::Ident(name)) => {
+                let value = self.variable(&name)?;
+                self.pos += 1;
+                Ok(value)
+            }
+            Some(Token::LParen) => {
+                self.pos += 1;
+                let value = self.expr()?;
+                self.expect(Token::RParen)?;
+                Ok(value)
+            }
+            _ => Err(self.error("expected a number, a variable or `(`")),
+        }
+    }
+
+    fn variable(&mut self, name: &str) -> Result<Value, ParseError> {
+        if let Some(value) = self.named.get(name) {
+            return Ok(value.clone());
+        }
+        if let Some(leaf) = self.leaves.get(name) {
+            return Ok(leaf.clone());
+        }
+        let data = *self.bindings.get(name)
+            .ok_or_else(|| self.error(&format!("unbound variable `{}`", name)))?;
+        let leaf = Value::leaf(name, data);
+        self.leaves.insert(name.to_string(), leaf.clone());
+        Ok(leaf)
+    }
+}
+
+/// Graph built by `parse_graph`
+#[derive(Debug, Clone)]
+pub struct ParsedGraph {
+    // value of the last statement
+    pub root: Value,
+    // leaf of every variable used, by name
+    pub leaves: HashMap<String, Value>,
+}
+
+impl ParsedGraph {
+    /// Gradients of the root with respect to each variable, once `root.backward()` was called.
+    /// A variable is copied in the graph at each use, its gradient is the sum over the copies
+    pub fn gradients(&self) -> HashMap<String, f64> {
+        let mut grads = Gradients::new();
+        accumulate_gradients(&self.root, &mut grads);
+        self.leaves.iter()
+            .map(|(name, leaf)| (name.clone(), grads.get(&leaf.id()).copied().unwrap_or(0.)))
+            .collect()
+    }
+}
+
+/// Builds the labelled computational graph described by `source`, e.g. `L = (a + b) * d^2`.
+///
+/// Statements are separated by `;` or new lines and may be named, later statements can
+/// reference earlier names (`c = a + b; L = c * d^2`). The last statement is the returned root.
+/// Variables are looked up in `bindings` and become leaves labelled with their name, unnamed
+/// intermediate nodes are labelled `t0`, `t1`, ... Exponents must be constants and
+/// `tanh`, `sigmoid`, `relu`, `exp`, `ln` (or `log`) and `abs` can be called like `tanh(a)`
+pub fn parse_graph(source: &str, bindings: &HashMap<&str, f64>) -> Result<ParsedGraph, ParseError> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        pos: 0,
+        end: source.len(),
+        bindings,
+        leaves: HashMap::new(),
+        named: HashMap::new(),
+        n_intermediate: 0,
+    };
+
+    let mut root = None;
+    while parser.pos < parser.tokens.len() {
+        if parser.peek() == Some(&Token::Separator) {
+            parser.pos += 1;
+            continue;
+        }
+        root = Some(parser.statement()?);
+        match parser.peek() {
+            None | Some(Token::Separator) => {}
+            Some(_) => return Err(parser.error("expected an operator or the end of the statement")),
+        }
+    }
+    let root = root.ok_or(ParseError { message: "empty expression".to_string(), position: 0 })?;
+    Ok(ParsedGraph { root, leaves: parser.leaves })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(source: &str) -> f64 {
+        let bindings = HashMap::from([("a", 2.0), ("b", 3.0), ("c", 5.0)]);
+        parse_graph(source, &bindings).unwrap().root.data()
+    }
+
+    fn error(source: &str) -> ParseError {
+        parse_graph(source, &HashMap::from([("a", 2.0), ("b", 3.0)])).unwrap_err()
+    }
+
+    #[test]
+    fn precedence_and_associativity() {
+        assert_eq!(eval("a + b * c"), 17.);
+        assert_eq!(eval("(a + b) * c"), 25.);
+        // left associative
+        assert_eq!(eval("a - b - c"), -6.);
+        assert_eq!(eval("c / a / b"), 5. / 6.);
+        // right associative, `a^(2^3)`
+        assert_eq!(eval("a^2^3"), 256.);
+        assert_eq!(eval("a^-1"), 0.5);
+        // unary minus binds looser than `^` and tighter than `*`
+        assert_eq!(eval("-a^2"), -4.);
+        assert_eq!(eval("-a * b"), -6.);
+        assert_eq!(eval("a - -b"), 5.);
+    }
+
+    #[test]
+    fn function_calls() {
+        assert!((eval("exp(ln(a))") - 2.).abs() < 1e-12);
+        assert_eq!(eval("relu(a - b) + abs(a - b)"), 1.);
+        assert_eq!(eval("tanh(0 * a)"), 0.);
+        assert!((eval("sigmoid(a - a)") - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn leaves_give_the_gradient_of_each_variable() {
+        let bindings = HashMap::from([("a", 2.0), ("b", -3.0), ("d", 1.5)]);
+        let mut graph = parse_graph("c = a + b; L = c * a * d^2", &bindings).unwrap();
+        assert_eq!(graph.root.label(), "L");
+        assert_eq!(graph.leaves.len(), 3);
+        assert_eq!(graph.leaves["a"].label(), "a");
+        graph.root.backward();
+        let grads = graph.gradients();
+        // L = (a + b) a d^2, `a` is used twice
+        assert_eq!(grads["a"], (2. * 2. - 3.) * 1.5 * 1.5);
+        assert_eq!(grads["b"], 2. * 1.5 * 1.5);
+        assert_eq!(grads["d"], (2. - 3.) * 2. * 2. * 1.5);
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_token() {
+        assert_eq!(error("a + x"), ParseError { message: "unbound variable `x`".to_string(), position: 4 });
+        assert_eq!(error("a^b").position, 2);
+        assert_eq!(error("a^b").message, "exponent must be a number");
+        assert_eq!(error("foo(a)").message, "unknown function `foo`");
+        assert_eq!(error("(a + b").position, 6);
+        assert_eq!(error("a b").position, 2);
+        assert_eq!(error("a + $").position, 4);
+        assert_eq!(error("c = a; c = b").position, 7);
+        assert_eq!(error(" ; ").message, "empty expression");
+    }
+}
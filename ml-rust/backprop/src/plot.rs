@@ -0,0 +1,101 @@
+use std::error::Error;
+
+use plotters::prelude::*;
+use plotters::style::full_palette::{ORANGE, PURPLE};
+
+use super::datasets::Samples;
+
+const CLASS_COLORS: [&RGBColor; 6] = [&BLUE, &RED, &GREEN, &ORANGE, &PURPLE, &CYAN];
+
+fn class_color(class: usize) -> &'static RGBColor {
+    CLASS_COLORS[class % CLASS_COLORS.len()]
+}
+
+/// Draws the loss of each epoch
+pub fn draw_loss_curve(losses: &[f64], file_name: &str, caption: &str) -> Result<(), Box<dyn Error>> {
+    let drawing_area_width = 1000;
+    let drawing_area_height = 1000;
+    let root_area = BitMapBackend::new(&file_name, (drawing_area_width, drawing_area_height)).into_drawing_area();
+    root_area.fill(&WHITE)?;
+
+    let max_loss = losses.iter().copied().fold(0., f64::max);
+    let mut chart = ChartBuilder::on(&root_area)
+        .set_label_area_size(LabelAreaPosition::Left, 40)
+        .set_label_area_size(LabelAreaPosition::Bottom, 40)
+        .margin_bottom(50)
+        .margin_left(80)
+        .margin_right(10)
+        .caption(caption, ("sans-serif", 40))
+        .build_cartesian_2d(0..losses.len(), 0f64..max_loss * 1.05)?;
+
+    chart.configure_mesh()
+        .x_desc("epoch")
+        .y_desc("loss")
+        .draw()?;
+    chart.draw_series(LineSeries::new(losses.iter().copied().enumerate(), &RED))?
+        .label("loss");
+    root_area.present()?;
+    println!("Loss curve saved to {}", file_name);
+
+    Ok(())
+}
+
+/// Colours the plane spanned by the features `axes` with the class predicted by `classify`
+/// and draws the samples on top of it
+pub fn draw_decision_boundary(
+    samples: &Samples,
+    axes: (usize, usize),
+    classify: impl Fn(f64, f64) -> usize,
+    file_name: &str,
+) -> Result<(), Box<dyn Error>> {
+    let drawing_area_width = 1000;
+    let drawing_area_height = 1000;
+    let root_area = BitMapBackend::new(&file_name, (drawing_area_width, drawing_area_height)).into_drawing_area();
+    root_area.fill(&WHITE)?;
+
+    let range = |j: usize| {
+        let (min, max) = samples.features.iter()
+            .map(|x| x[j])
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| (lo.min(v), hi.max(v)));
+        let padding = 0.1 * (max - min).max(1e-6);
+        (min - padding, max + padding)
+    };
+    let (x_min, x_max) = range(axes.0);
+    let (y_min, y_max) = range(axes.1);
+
+    let mut chart = ChartBuilder::on(&root_area)
+        .set_label_area_size(LabelAreaPosition::Left, 40)
+        .set_label_area_size(LabelAreaPosition::Bottom, 40)
+        .margin_bottom(50)
+        .margin_left(80)
+        .margin_right(10)
+        .caption("Decision boundary", ("sans-serif", 40))
+        .build_cartesian_2d(x_min..x_max, y_min..y_max)?;
+
+    chart.configure_mesh()
+        .x_desc(samples.feature_names[axes.0].as_str())
+        .y_desc(samples.feature_names[axes.1].as_str())
+        .draw()?;
+
+    let resolution = 100;
+    let (dx, dy) = ((x_max - x_min) / resolution as f64, (y_max - y_min) / resolution as f64);
+    chart.draw_series((0..resolution).flat_map(|i| (0..resolution).map(move |j| (i, j))).map(|(i, j)| {
+        let (x, y) = (x_min + i as f64 * dx, y_min + j as f64 * dy);
+        let color = class_color(classify(x + dx / 2., y + dy / 2.)).mix(0.2);
+        Rectangle::new([(x, y), (x + dx, y + dy)], color.filled())
+    }))?;
+
+    for class in 0..samples.n_classes {
+        let points = samples.features.iter().zip(samples.targets.iter())
+            .filter(move |(_, &t)| t == class)
+            .map(|(x, _)| (x[axes.0], x[axes.1]));
+        chart.draw_series(points.map(|p| Circle::new(p, 5, class_color(class).filled())))?
+            .label(format!("Class {}", class))
+            .legend(move |(x, y)| Circle::new((x, y), 5, class_color(class).filled()));
+    }
+    chart.configure_series_labels().border_style(BLACK).background_style(WHITE).draw()?;
+    root_area.present()?;
+    println!("Decision boundary saved to {}", file_name);
+
+    Ok(())
+}
@@ -0,0 +1,460 @@
+use rand::Rng;
+
+use super::datasets::Sequence;
+use super::nn::{argmax, inputs, softmax_cross_entropy, Activation, Layer, Model};
+use super::optim::{accumulate_gradients, Gradients};
+use super::train::TrainingSet;
+use super::Value;
+
+// input of the gates, the features followed by the hidden state
+fn concat(x: &[Value], h: &[Value]) -> Vec<Value> {
+    x.iter().chain(h.iter()).cloned().collect()
+}
+
+/// State carried from one time step to the next, `c` is the cell state of the LSTM and empty otherwise
+#[derive(Debug, Clone)]
+pub struct State {
+    pub h: Vec<Value>,
+    pub c: Vec<Value>,
+}
+
+impl State {
+    pub fn zeros(hidden_size: usize, with_cell: bool) -> State {
+        let zeros = |name: &str| (0..hidden_size).map(|j| Value::leaf(&format!("{}{}", name, j), 0.)).collect();
+        State { h: zeros("h"), c: if with_cell { zeros("c") } else { Vec::new() } }
+    }
+
+    /// Same values as new leaves, so that back-propagation stops at this step
+    pub fn detach(&self) -> State {
+        State {
+            h: self.h.iter().map(Value::detach).collect(),
+            c: self.c.iter().map(Value::detach).collect(),
+        }
+    }
+}
+
+/// One time step of a recurrent network
+pub trait RecurrentCell: Sync {
+    fn hidden_size(&self) -> usize;
+
+    fn initial_state(&self) -> State;
+
+    fn step(&self, x: &[Value], state: &State) -> State;
+
+    fn parameters(&self) -> Vec<&Value>;
+
+    fn parameters_mut(&mut self) -> Vec<&mut Value>;
+}
+
+/// Elman network, `h' = tanh(W [x, h] + b)`
+#[derive(Debug, Clone)]
+pub struct RNNCell {
+    hidden: Layer,
+}
+
+impl RNNCell {
+    pub fn new(n_inputs: usize, hidden_size: usize, rng: &mut impl Rng) -> RNNCell {
+        RNNCell { hidden: Layer::new(n_inputs + hidden_size, hidden_size, Activation::Tanh, 0, rng) }
+    }
+}
+
+impl RecurrentCell for RNNCell {
+    fn hidden_size(&self) -> usize {
+        self.hidden.neurons().len()
+    }
+
+    fn initial_state(&self) -> State {
+        State::zeros(self.hidden_size(), false)
+    }
+
+    fn step(&self, x: &[Value], state: &State) -> State {
+        State { h: self.hidden.forward(&concat(x, &state.h)), c: Vec::new() }
+    }
+
+    fn parameters(&self) -> Vec<&Value> {
+        self.hidden.parameters()
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut Value> {
+        self.hidden.parameters_mut()
+    }
+}
+
+/// Gated recurrent unit (Cho et al., 2014), with the reset gate applied after the hidden
+/// projection as in PyTorch. The other form, `tanh(W [x, r h])`, copies the whole hidden state
+/// into every candidate unit and makes the graph grow much faster
+#[derive(Debug, Clone)]
+pub struct GRUCell {
+    // update gate z, reset gate r and the input and hidden projections of the candidate state n
+    update: Layer,
+    reset: Layer,
+    candidate_input: Layer,
+    candidate_hidden: Layer,
+}
+
+impl GRUCell {
+    pub fn new(n_inputs: usize, hidden_size: usize, rng: &mut impl Rng) -> GRUCell {
+        let n = n_inputs + hidden_size;
+        GRUCell {
+            update: Layer::new(n, hidden_size, Activation::Sigmoid, 0, rng),
+            reset: Layer::new(n, hidden_size, Activation::Sigmoid, 1, rng),
+            candidate_input: Layer::new(n_inputs, hidden_size, Activation::Linear, 2, rng),
+            candidate_hidden: Layer::new(hidden_size, hidden_size, Activation::Linear, 3, rng),
+        }
+    }
+}
+
+impl RecurrentCell for GRUCell {
+    fn hidden_size(&self) -> usize {
+        self.update.neurons().len()
+    }
+
+    fn initial_state(&self) -> State {
+        State::zeros(self.hidden_size(), false)
+    }
+
+    // h' = (1 - z) n + z h with n = tanh(W_in x + b_in + r (W_hn h + b_hn))
+    fn step(&self, x: &[Value], state: &State) -> State {
+        let xh = concat(x, &state.h);
+        let z = self.update.forward(&xh);
+        let r = self.reset.forward(&xh);
+        let n = self.candidate_input.forward(x).into_iter()
+            .zip(r.into_iter().zip(self.candidate_hidden.forward(&state.h)))
+            .map(|(wx, (r, wh))| (wx + r * wh).tanh());
+        let h = z.into_iter().zip(n).zip(state.h.iter())
+            .map(|((z, n), h)| (Value::scalar(1.) - z.clone()) * n + z * h.clone())
+            .collect();
+        State { h, c: Vec::new() }
+    }
+
+    fn parameters(&self) -> Vec<&Value> {
+        [&self.update, &self.reset, &self.candidate_input, &self.candidate_hidden]
+            .into_iter()
+            .flat_map(|l| l.parameters())
+            .collect()
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut Value> {
+        [&mut self.update, &mut self.reset, &mut self.candidate_input, &mut self.candidate_hidden]
+            .into_iter()
+            .flat_map(|l| l.parameters_mut())
+            .collect()
+    }
+}
+
+/// Long short-term memory (Hochreiter & Schmidhuber, 1997)
+#[derive(Debug, Clone)]
+pub struct LSTMCell {
+    input: Layer,
+    forget: Layer,
+    output: Layer,
+    candidate: Layer,
+}
+
+impl LSTMCell {
+    pub fn new(n_inputs: usize, hidden_size: usize, rng: &mut impl Rng) -> LSTMCell {
+        let n = n_inputs + hidden_size;
+        LSTMCell {
+            input: Layer::new(n, hidden_size, Activation::Sigmoid, 0, rng),
+            forget: Layer::new(n, hidden_size, Activation::Sigmoid, 1, rng),
+            output: Layer::new(n, hidden_size, Activation::Sigmoid, 2, rng),
+            candidate: Layer::new(n, hidden_size, Activation::Tanh, 3, rng),
+        }
+    }
+}
+
+impl RecurrentCell for LSTMCell {
+    fn hidden_size(&self) -> usize {
+        self.input.neurons().len()
+    }
+
+    fn initial_state(&self) -> State {
+        State::zeros(self.hidden_size(), true)
+    }
+
+    // c' = f c + i g, h' = o tanh(c')
+    fn step(&self, x: &[Value], state: &State) -> State {
+        let xh = concat(x, &state.h);
+        let i = self.input.forward(&xh);
+        let f = self.forget.forward(&xh);
+        let o = self.output.forward(&xh);
+        let g = self.candidate.forward(&xh);
+        let c: Vec<Value> = f.into_iter().zip(state.c.iter()).zip(i.into_iter().zip(g))
+            .map(|((f, c), (i, g))| f * c.clone() + i * g)
+            .collect();
+        let h = o.into_iter().zip(c.iter()).map(|(o, c)| o * c.clone().tanh()).collect();
+        State { h, c }
+    }
+
+    fn parameters(&self) -> Vec<&Value> {
+        [&self.input, &self.forget, &self.output, &self.candidate].into_iter().flat_map(|l| l.parameters()).collect()
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut Value> {
+        [&mut self.input, &mut self.forget, &mut self.output, &mut self.candidate]
+            .into_iter()
+            .flat_map(|l| l.parameters_mut())
+            .collect()
+    }
+}
+
+/// Recurrent cell followed by a linear layer giving the logits of every time step
+#[derive(Debug, Clone)]
+pub struct SequenceClassifier<C: RecurrentCell> {
+    cell: C,
+    head: Layer,
+}
+
+impl<C: RecurrentCell> SequenceClassifier<C> {
+    pub fn new(cell: C, n_classes: usize, rng: &mut impl Rng) -> SequenceClassifier<C> {
+        let head = Layer::new(cell.hidden_size(), n_classes, Activation::Linear, 9, rng);
+        SequenceClassifier { cell, head }
+    }
+
+    pub fn cell(&self) -> &C {
+        &self.cell
+    }
+
+    /// Unrolls the cell over `steps` from `state`, returning the logits of each step and the last state
+    pub fn forward(&self, steps: &[Vec<f64>], state: State) -> (Vec<Vec<Value>>, State) {
+        let mut state = state;
+        let mut logits = Vec::with_capacity(steps.len());
+        for features in steps {
+            state = self.cell.step(&inputs(features), &state);
+            logits.push(self.head.forward(&state.h));
+        }
+        (logits, state)
+    }
+
+    /// Predicted class of each step, the state is detached at every step as no gradient is needed
+    pub fn predict(&self, steps: &[Vec<f64>]) -> Vec<usize> {
+        let mut state = self.cell.initial_state();
+        steps.iter()
+            .map(|features| {
+                let (logits, next) = self.forward(std::slice::from_ref(features), state.clone());
+                state = next.detach();
+                argmax(&logits[0].iter().map(|l| l.data()).collect::<Vec<_>>())
+            })
+            .collect()
+    }
+
+    pub fn parameters(&self) -> Vec<&Value> {
+        self.cell.parameters().into_iter().chain(self.head.parameters()).collect()
+    }
+
+    pub fn parameters_mut(&mut self) -> Vec<&mut Value> {
+        self.cell.parameters_mut().into_iter().chain(self.head.parameters_mut()).collect()
+    }
+}
+
+impl<C: RecurrentCell> Model for SequenceClassifier<C> {
+    // a sample of a single time step, read from the initial state
+    fn forward_sample(&self, features: &[f64]) -> Vec<Value> {
+        let (mut logits, _) = self.forward(&[features.to_vec()], self.cell.initial_state());
+        logits.remove(0)
+    }
+
+    fn parameters(&self) -> Vec<&Value> {
+        SequenceClassifier::parameters(self)
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut Value> {
+        SequenceClassifier::parameters_mut(self)
+    }
+}
+
+/// Truncated back-propagation through time: the sequence is unrolled `truncation` steps at a time,
+/// the loss of each window is back-propagated and the state is detached before the next one.
+/// Returns the summed cross entropy of the steps and the gradients summed over the windows.
+///
+/// A `Value` owns its children, so a graph unrolled over the window would copy the previous state
+/// into every unit reading it and grow exponentially with the number of steps. Instead each step
+/// is built alone from its input state as leaves, going backwards through the window: the
+/// gradients of the later steps with respect to its output state are already known and enter its
+/// graph as constant weights, and the gradients of its input state are passed on to the step before.
+/// The gradients are the same as through the unrolled graph for a cost linear in the window length
+pub fn bptt_gradients<C: RecurrentCell>(
+    model: &SequenceClassifier<C>,
+    sequence: &Sequence,
+    truncation: usize,
+) -> (f64, Gradients) {
+    let truncation = truncation.max(1);
+    let mut state = model.cell.initial_state();
+    let mut loss_sum = 0.;
+    let mut grads = Gradients::new();
+    for (steps, targets) in sequence.inputs.chunks(truncation).zip(sequence.targets.chunks(truncation)) {
+        // input state of every step of the window, as leaves
+        let mut states = Vec::with_capacity(steps.len());
+        for features in steps {
+            let next = model.cell.step(&inputs(features), &state).detach();
+            states.push(std::mem::replace(&mut state, next));
+        }
+
+        // gradients of the loss of the later steps with respect to the output state of the current one
+        let mut grad_h = vec![0.; state.h.len()];
+        let mut grad_c = vec![0.; state.c.len()];
+        for ((features, &target), input) in steps.iter().zip(targets.iter()).zip(states.iter()).rev() {
+            let output = model.cell.step(&inputs(features), input);
+            let loss = softmax_cross_entropy(&model.head.forward(&output.h), target);
+            loss_sum += loss.data();
+            let carried = output.h.into_iter().zip(grad_h.iter()).chain(output.c.into_iter().zip(grad_c.iter()));
+            let mut surrogate = loss + Value::sum(carried.map(|(v, &g)| v * g));
+            surrogate.backward();
+
+            let mut step_grads = Gradients::new();
+            accumulate_gradients(&surrogate, &mut step_grads);
+            let input_grads = |values: &[Value]| values.iter().map(|v| step_grads.get(&v.id()).copied().unwrap_or_default()).collect();
+            grad_h = input_grads(&input.h);
+            grad_c = input_grads(&input.c);
+            for (id, grad) in step_grads {
+                *grads.entry(id).or_insert(0.) += grad;
+            }
+        }
+    }
+    (loss_sum, grads)
+}
+
+/// Fraction of the time steps whose class is predicted correctly
+pub fn sequence_accuracy<C: RecurrentCell>(model: &SequenceClassifier<C>, sequences: &[Sequence]) -> f64 {
+    let (correct, total) = sequences.iter().fold((0, 0), |(correct, total), s| {
+        let hits = model.predict(&s.inputs).iter().zip(s.targets.iter()).filter(|(p, y)| p == y).count();
+        (correct + hits, total + s.targets.len())
+    });
+    correct as f64 / total as f64
+}
+
+/// Sequences trained on with truncated BPTT by `train::fit`, every time step is a prediction
+#[derive(Debug, Clone, Copy)]
+pub struct SequenceSet<'a> {
+    pub sequences: &'a [Sequence],
+    pub truncation: usize,
+}
+
+impl<C: RecurrentCell> TrainingSet<SequenceClassifier<C>> for SequenceSet<'_> {
+    fn len(&self) -> usize {
+        self.sequences.len()
+    }
+
+    fn n_predictions(&self, i: usize) -> usize {
+        self.sequences[i].targets.len()
+    }
+
+    fn sample_gradients(&self, model: &SequenceClassifier<C>, i: usize) -> (f64, Gradients) {
+        bptt_gradients(model, &self.sequences[i], self.truncation)
+    }
+
+    fn accuracy(&self, model: &SequenceClassifier<C>) -> f64 {
+        sequence_accuracy(model, self.sequences)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::diagnostics::check_gradients;
+    use crate::optim::Adam;
+    use crate::test_util::weighted_sum;
+    use crate::train::{fit, TrainConfig};
+
+    // two time steps of two features, the loss weights the last hidden state
+    fn unrolled_loss(cell: &impl RecurrentCell, x: &[Value]) -> Value {
+        let state = cell.step(&x[2..], &cell.step(&x[..2], &cell.initial_state()));
+        weighted_sum(&state.h)
+    }
+
+    const POINT: [f64; 4] = [0.5, -1.2, 0.8, 0.3];
+
+    #[test]
+    fn cell_gradients() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let rnn = RNNCell::new(2, 3, &mut rng);
+        let gru = GRUCell::new(2, 3, &mut rng);
+        let lstm = LSTMCell::new(2, 3, &mut rng);
+        for check in [
+            check_gradients(|x| unrolled_loss(&rnn, x), &POINT, 1e-5),
+            check_gradients(|x| unrolled_loss(&gru, x), &POINT, 1e-5),
+            check_gradients(|x| unrolled_loss(&lstm, x), &POINT, 1e-5),
+        ] {
+            assert!(check.passed(1e-6), "{:?}", check);
+        }
+    }
+
+    #[test]
+    fn detached_state_stops_gradients() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let model = SequenceClassifier::new(RNNCell::new(1, 2, &mut rng), 2, &mut rng);
+        let sequence = Sequence { inputs: vec![vec![1.], vec![0.], vec![1.]], targets: vec![1, 1, 0] };
+        // with windows of one step the loss of each step only reaches the parameters
+        // through that step, so the gradients are the sum of three single step sequences
+        let (loss, grads) = bptt_gradients(&model, &sequence, 1);
+        let (full_loss, _) = bptt_gradients(&model, &sequence, 3);
+        assert!((loss - full_loss).abs() < 1e-12, "detaching doesn't change the forward pass");
+
+        let mut state = model.cell().initial_state();
+        let mut expected = Gradients::new();
+        for (x, &y) in sequence.inputs.iter().zip(sequence.targets.iter()) {
+            let (logits, next) = model.forward(std::slice::from_ref(x), state.detach());
+            let mut step_loss = softmax_cross_entropy(&logits[0], y);
+            step_loss.backward();
+            accumulate_gradients(&step_loss, &mut expected);
+            state = next;
+        }
+        for p in model.parameters() {
+            let (got, want) = (grads[&p.id()], expected[&p.id()]);
+            assert!((got - want).abs() < 1e-12, "{}: {} != {}", p.label(), got, want);
+        }
+    }
+
+    // gradients of a window back-propagated through the whole unrolled graph
+    fn unrolled_gradients<C: RecurrentCell>(model: &SequenceClassifier<C>, sequence: &Sequence) -> (f64, Gradients) {
+        let (logits, _) = model.forward(&sequence.inputs, model.cell().initial_state());
+        let mut loss = Value::sum(logits.iter().zip(sequence.targets.iter()).map(|(l, &y)| softmax_cross_entropy(l, y)));
+        loss.backward();
+        let mut grads = Gradients::new();
+        accumulate_gradients(&loss, &mut grads);
+        (loss.data(), grads)
+    }
+
+    #[test]
+    fn step_by_step_bptt_matches_the_unrolled_graph() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let sequence = Sequence {
+            inputs: vec![vec![0.3, -1.], vec![1.2, 0.4], vec![-0.7, 0.9]],
+            targets: vec![0, 2, 1],
+        };
+        let gru = SequenceClassifier::new(GRUCell::new(2, 3, &mut rng), 3, &mut rng);
+        let lstm = SequenceClassifier::new(LSTMCell::new(2, 3, &mut rng), 3, &mut rng);
+        for ((loss, grads), (expected_loss, expected), params) in [
+            (bptt_gradients(&gru, &sequence, 3), unrolled_gradients(&gru, &sequence), gru.parameters()),
+            (bptt_gradients(&lstm, &sequence, 3), unrolled_gradients(&lstm, &sequence), lstm.parameters()),
+        ] {
+            assert!((loss - expected_loss).abs() < 1e-12);
+            for p in params {
+                let (got, want) = (grads[&p.id()], expected[&p.id()]);
+                assert!((got - want).abs() < 1e-10, "{}: {} != {}", p.label(), got, want);
+            }
+        }
+    }
+
+    #[test]
+    fn fit_trains_sequence_classifiers() {
+        // the class of a step is its input delayed by one step, which needs the hidden state
+        let mut rng = StdRng::seed_from_u64(5);
+        let sequences: Vec<Sequence> = (0..10)
+            .map(|_| {
+                let bits: Vec<usize> = (0..5).map(|_| rng.random_range(0..2)).collect();
+                let targets = std::iter::once(0).chain(bits.iter().copied()).take(bits.len()).collect();
+                Sequence { inputs: bits.iter().map(|&b| vec![b as f64]).collect(), targets }
+            })
+            .collect();
+        let mut model = SequenceClassifier::new(GRUCell::new(1, 3, &mut rng), 2, &mut rng);
+        let config = TrainConfig { epochs: 60, batch_size: 5, log_every: 0, ..TrainConfig::default() };
+        let set = SequenceSet { sequences: &sequences, truncation: 5 };
+        let history = fit(&mut model, &set, &mut Adam::new(0.05), &config);
+        assert!(history.losses[59] < history.losses[0]);
+        assert!(history.accuracies[59] > 0.9, "{:?}", history.accuracies);
+    }
+}
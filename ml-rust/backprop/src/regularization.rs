@@ -0,0 +1,259 @@
+use num_traits::Pow;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use super::Value;
+
+/// Inverted dropout: in training mode each input is zeroed with probability `p` and the
+/// others are scaled by `1 / (1 - p)`, so that nothing has to change in evaluation mode
+#[derive(Debug, Clone)]
+pub struct Dropout {
+    p: f64,
+    training: bool,
+    rng: StdRng,
+}
+
+impl Dropout {
+    pub fn new(p: f64, seed: u64) -> Dropout {
+        assert!((0. ..1.).contains(&p), "dropout probability must be in [0, 1)");
+        Dropout { p, training: true, rng: StdRng::seed_from_u64(seed) }
+    }
+
+    pub fn train(&mut self) {
+        self.training = true;
+    }
+
+    pub fn eval(&mut self) {
+        self.training = false;
+    }
+
+    pub fn is_training(&self) -> bool {
+        self.training
+    }
+
+    pub fn forward(&mut self, x: &[Value]) -> Vec<Value> {
+        if !self.training || self.p == 0. {
+            return x.to_vec();
+        }
+        let scale = 1. / (1. - self.p);
+        x.iter()
+            .map(|xi| {
+                let keep = self.rng.random::<f64>() >= self.p;
+                xi.clone() * if keep { scale } else { 0. }
+            })
+            .collect()
+    }
+}
+
+/// Weight penalty added to the loss graph, `lambda * sum |w|` or `lambda * sum w^2`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Penalty {
+    L1(f64),
+    L2(f64),
+}
+
+impl Penalty {
+    pub fn loss(&self, params: &[&Value]) -> Value {
+        match *self {
+            Penalty::L1(lambda) => Value::sum(params.iter().map(|w| (*w).clone().abs())) * lambda,
+            Penalty::L2(lambda) => Value::sum(params.iter().map(|w| (*w).clone().pow(2))) * lambda,
+        }
+    }
+}
+
+// gamma * (x - mean) / sqrt(var + eps) + beta
+fn normalize(x: Value, mean: Value, var: Value, eps: f64, gamma: &Value, beta: &Value) -> Value {
+    gamma.clone() * ((x - mean) / (var + eps).pow(0.5)) + beta.clone()
+}
+
+/// Batch normalisation of each feature over the samples of a batch, with learnable scale
+/// `gamma` and shift `beta`. Evaluation mode uses running estimates of the mean and variance
+#[derive(Debug, Clone)]
+pub struct BatchNorm {
+    gamma: Vec<Value>,
+    beta: Vec<Value>,
+    running_mean: Vec<f64>,
+    running_var: Vec<f64>,
+    momentum: f64,
+    eps: f64,
+    training: bool,
+}
+
+impl BatchNorm {
+    pub fn new(n_features: usize, name: &str) -> BatchNorm {
+        BatchNorm {
+            gamma: (0..n_features).map(|j| Value::leaf(&format!("gamma{}_{}", name, j), 1.)).collect(),
+            beta: (0..n_features).map(|j| Value::leaf(&format!("beta{}_{}", name, j), 0.)).collect(),
+            running_mean: vec![0.; n_features],
+            running_var: vec![1.; n_features],
+            momentum: 0.1,
+            eps: 1e-5,
+            training: true,
+        }
+    }
+
+    pub fn train(&mut self) {
+        self.training = true;
+    }
+
+    pub fn eval(&mut self) {
+        self.training = false;
+    }
+
+    /// `batch[i][j]` is feature `j` of sample `i`, the output has the same shape
+    pub fn forward(&mut self, batch: &[Vec<Value>]) -> Vec<Vec<Value>> {
+        let n = batch.len() as f64;
+        let n_features = self.gamma.len();
+        let mut out: Vec<Vec<Value>> = vec![Vec::with_capacity(n_features); batch.len()];
+        for j in 0..n_features {
+            let column = || batch.iter().map(|sample| sample[j].clone());
+            let (mean, var) = if self.training {
+                let mean = Value::sum(column()) / n;
+                let var = Value::sum(column().map(|x| (x - mean.clone()).pow(2))) / n;
+                // the running variance uses the unbiased estimate
+                let unbiased = if n > 1. { var.data() * n / (n - 1.) } else { var.data() };
+                self.running_mean[j] = (1. - self.momentum) * self.running_mean[j] + self.momentum * mean.data();
+                self.running_var[j] = (1. - self.momentum) * self.running_var[j] + self.momentum * unbiased;
+                (mean, var)
+            } else {
+                (Value::scalar(self.running_mean[j]), Value::scalar(self.running_var[j]))
+            };
+            for (i, x) in column().enumerate() {
+                out[i].push(normalize(x, mean.clone(), var.clone(), self.eps, &self.gamma[j], &self.beta[j]));
+            }
+        }
+        out
+    }
+
+    pub fn parameters(&self) -> Vec<&Value> {
+        self.gamma.iter().chain(self.beta.iter()).collect()
+    }
+
+    pub fn parameters_mut(&mut self) -> Vec<&mut Value> {
+        self.gamma.iter_mut().chain(self.beta.iter_mut()).collect()
+    }
+}
+
+/// Layer normalisation over the features of a single sample, with learnable scale and shift
+#[derive(Debug, Clone)]
+pub struct LayerNorm {
+    gamma: Vec<Value>,
+    beta: Vec<Value>,
+    eps: f64,
+}
+
+impl LayerNorm {
+    pub fn new(n_features: usize, name: &str) -> LayerNorm {
+        LayerNorm {
+            gamma: (0..n_features).map(|j| Value::leaf(&format!("gamma{}_{}", name, j), 1.)).collect(),
+            beta: (0..n_features).map(|j| Value::leaf(&format!("beta{}_{}", name, j), 0.)).collect(),
+            eps: 1e-5,
+        }
+    }
+
+    pub fn forward(&self, x: &[Value]) -> Vec<Value> {
+        let n = x.len() as f64;
+        let mean = Value::sum(x.iter().cloned()) / n;
+        let var = Value::sum(x.iter().map(|xi| (xi.clone() - mean.clone()).pow(2))) / n;
+        x.iter()
+            .enumerate()
+            .map(|(j, xi)| normalize(xi.clone(), mean.clone(), var.clone(), self.eps, &self.gamma[j], &self.beta[j]))
+            .collect()
+    }
+
+    pub fn parameters(&self) -> Vec<&Value> {
+        self.gamma.iter().chain(self.beta.iter()).collect()
+    }
+
+    pub fn parameters_mut(&mut self) -> Vec<&mut Value> {
+        self.gamma.iter_mut().chain(self.beta.iter_mut()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::check_gradients;
+    use crate::test_util::{point, weighted_sum};
+
+    const POINT: [f64; 6] = [0.5, -1.2, 2.0, 0.3, -0.7, 1.1];
+
+    #[test]
+    fn dropout_gradients() {
+        let check = check_gradients(|x| {
+            // same seed on every call, so the finite differences see the same mask
+            weighted_sum(&Dropout::new(0.5, 3).forward(x))
+        }, &POINT, 1e-5);
+        assert!(check.passed(1e-6), "{:?}", check);
+    }
+
+    #[test]
+    fn dropout_is_identity_in_eval_mode() {
+        let mut dropout = Dropout::new(0.5, 3);
+        dropout.eval();
+        let x = crate::nn::inputs(&POINT);
+        let out = dropout.forward(&x);
+        assert!(out.iter().zip(POINT.iter()).all(|(o, &p)| o.data() == p));
+    }
+
+    #[test]
+    fn penalty_gradients() {
+        for penalty in [Penalty::L1(0.1), Penalty::L2(0.1)] {
+            let check = check_gradients(|w| penalty.loss(&w.iter().collect::<Vec<_>>()), &POINT, 1e-5);
+            assert!(check.passed(1e-6), "{:?} {:?}", penalty, check);
+        }
+    }
+
+    #[test]
+    fn batch_norm_gradients() {
+        // 3 samples of 2 features
+        let check = check_gradients(|x| {
+            let batch: Vec<Vec<Value>> = x.chunks(2).map(|c| c.to_vec()).collect();
+            let out: Vec<Value> = BatchNorm::new(2, "0").forward(&batch).into_iter().flatten().collect();
+            weighted_sum(&out)
+        }, &POINT, 1e-5);
+        assert!(check.passed(1e-5), "{:?}", check);
+    }
+
+    #[test]
+    fn layer_norm_gradients() {
+        let norm = LayerNorm::new(POINT.len(), "0");
+        let check = check_gradients(|x| weighted_sum(&norm.forward(x)), &POINT, 1e-5);
+        assert!(check.passed(1e-5), "{:?}", check);
+    }
+
+    // replaces gamma and beta by values perturbed by `check_gradients`, whose gradients are then
+    // summed over every use of the parameter by its id, e.g. once per sample of a batch
+    fn set_parameters(params: Vec<&mut Value>, values: &[Value]) {
+        assert_eq!(params.len(), values.len());
+        for (param, value) in params.into_iter().zip(values.iter()) {
+            *param = value.clone();
+        }
+    }
+
+    #[test]
+    fn batch_norm_parameter_gradients() {
+        // 3 samples of 2 features, then gamma and beta
+        let check = check_gradients(|x| {
+            let batch: Vec<Vec<Value>> = x[..6].chunks(2).map(|c| c.to_vec()).collect();
+            let mut norm = BatchNorm::new(2, "0");
+            set_parameters(norm.parameters_mut(), &x[6..]);
+            let out: Vec<Value> = norm.forward(&batch).into_iter().flatten().collect();
+            weighted_sum(&out)
+        }, &point(10), 1e-5);
+        assert!(check.passed(1e-5), "{:?}", check);
+        assert!(check.analytic[6..].iter().all(|&g| g != 0.), "{:?}", check);
+    }
+
+    #[test]
+    fn layer_norm_parameter_gradients() {
+        let n = POINT.len();
+        let check = check_gradients(|x| {
+            let mut norm = LayerNorm::new(n, "0");
+            set_parameters(norm.parameters_mut(), &x[n..]);
+            weighted_sum(&norm.forward(&x[..n]))
+        }, &point(3 * n), 1e-5);
+        assert!(check.passed(1e-5), "{:?}", check);
+        assert!(check.analytic[n..].iter().all(|&g| g != 0.), "{:?}", check);
+    }
+}
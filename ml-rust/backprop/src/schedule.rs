@@ -0,0 +1,69 @@
+use std::f64::consts::PI;
+
+/// Learning rate as a function of the epoch, relative to the optimizer's initial learning rate
+#[derive(Debug, Clone, PartialEq)]
+pub enum LrSchedule {
+    Constant,
+    // multiply by `gamma` every `step_size` epochs
+    Step { step_size: usize, gamma: f64 },
+    // multiply by `gamma` every epoch
+    Exponential { gamma: f64 },
+    // half a cosine from the initial rate down to `min_lr` over `t_max` epochs
+    CosineAnnealing { t_max: usize, min_lr: f64 },
+    // linear increase from `base_lr / epochs` to `base_lr` during the first `epochs` epochs,
+    // then `then` starting over from epoch 0
+    Warmup { epochs: usize, then: Box<LrSchedule> },
+}
+
+impl LrSchedule {
+    pub fn learning_rate(&self, base_lr: f64, epoch: usize) -> f64 {
+        match self {
+            LrSchedule::Constant => base_lr,
+            LrSchedule::Step { step_size, gamma } => base_lr * gamma.powi((epoch / (*step_size).max(1)) as i32),
+            LrSchedule::Exponential { gamma } => base_lr * gamma.powi(epoch as i32),
+            LrSchedule::CosineAnnealing { t_max, min_lr } => {
+                let progress = epoch.min(*t_max) as f64 / (*t_max).max(1) as f64;
+                min_lr + (base_lr - min_lr) * (1. + (PI * progress).cos()) / 2.
+            }
+            LrSchedule::Warmup { epochs, then } => {
+                if epoch < *epochs {
+                    then.learning_rate(base_lr, 0) * (epoch + 1) as f64 / *epochs as f64
+                } else {
+                    then.learning_rate(base_lr, epoch - epochs)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-12
+    }
+
+    #[test]
+    fn rates_at_the_boundary_epochs() {
+        let step = LrSchedule::Step { step_size: 10, gamma: 0.5 };
+        let rates: Vec<f64> = [0, 9, 10, 19, 20].iter().map(|&e| step.learning_rate(1., e)).collect();
+        assert_eq!(rates, vec![1., 1., 0.5, 0.5, 0.25]);
+
+        let exponential = LrSchedule::Exponential { gamma: 0.9 };
+        assert_eq!(exponential.learning_rate(2., 0), 2.);
+        assert!(close(exponential.learning_rate(2., 2), 2. * 0.81));
+
+        // at 0, T/2 and T, then held at the minimum
+        let cosine = LrSchedule::CosineAnnealing { t_max: 10, min_lr: 0.1 };
+        assert_eq!(cosine.learning_rate(1., 0), 1.);
+        assert!(close(cosine.learning_rate(1., 5), 0.55));
+        assert!(close(cosine.learning_rate(1., 10), 0.1));
+        assert!(close(cosine.learning_rate(1., 15), 0.1));
+
+        // linear ramp up to the base rate at the last warmup epoch, then the step schedule from its epoch 0
+        let warmup = LrSchedule::Warmup { epochs: 4, then: Box::new(LrSchedule::Step { step_size: 2, gamma: 0.5 }) };
+        let rates: Vec<f64> = (0..7).map(|e| warmup.learning_rate(1., e)).collect();
+        assert_eq!(rates, vec![0.25, 0.5, 0.75, 1., 1., 1., 0.5]);
+    }
+}
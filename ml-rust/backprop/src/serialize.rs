@@ -0,0 +1,347 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::{next_id, Value};
+
+/// Version of the on-disk format, bumped whenever `Value` or `ParameterSet`
+/// change in a way that breaks older files
+pub const FORMAT_VERSION: u32 = 2;
+
+/// Encoding used for a checkpoint file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Json,
+    Binary,
+}
+
+/// Envelope written to disk, so that the version is checked before the payload is used
+#[derive(Serialize, Deserialize)]
+struct Versioned<T> {
+    version: u32,
+    payload: T,
+}
+
+#[derive(Debug)]
+pub struct VersionMismatch {
+    pub found: u32,
+    pub expected: u32,
+}
+
+impl fmt::Display for VersionMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unsupported format version {}, expected {}", self.found, self.expected)
+    }
+}
+
+impl Error for VersionMismatch {}
+
+/// Stored parameters that don't fit the values they are loaded into
+#[derive(Debug)]
+pub struct ParameterMismatch {
+    pub message: String,
+}
+
+impl fmt::Display for ParameterMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "parameters don't match: {}", self.message)
+    }
+}
+
+impl Error for ParameterMismatch {}
+
+fn mismatch(message: String) -> Box<dyn Error> {
+    Box::new(ParameterMismatch { message })
+}
+
+/// A single trainable parameter, identified by the label of the `Value` it was taken from.
+/// Ids are only unique within a process, a model built in another run gets different ones
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Parameter {
+    pub label: String,
+    pub data: f64,
+}
+
+/// Parameters of a multi layer perceptron, without the computational graph built on top of them
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ParameterSet {
+    // number of units of each layer, input layer first
+    pub layer_sizes: Vec<usize>,
+    pub params: Vec<Parameter>,
+}
+
+impl ParameterSet {
+    pub fn from_values(layer_sizes: &[usize], values: &[Value]) -> ParameterSet {
+        ParameterSet {
+            layer_sizes: layer_sizes.to_vec(),
+            params: values.iter().map(|v| Parameter {
+                label: v.label.clone(),
+                data: v.data,
+            }).collect(),
+        }
+    }
+
+    /// Copies the stored data back into `values`, matching parameters by label. Fails without
+    /// changing anything unless every value has exactly one stored parameter and none is left over
+    pub fn apply_to(&self, values: &mut [&mut Value]) -> Result<(), Box<dyn Error>> {
+        if values.len() != self.params.len() {
+            return Err(mismatch(format!("{} stored parameters for {} values", self.params.len(), values.len())));
+        }
+        let mut by_label = HashMap::new();
+        for param in self.params.iter() {
+            if by_label.insert(param.label.as_str(), param.data).is_some() {
+                return Err(mismatch(format!("parameter {} is stored twice", param.label)));
+            }
+        }
+        let data = values.iter()
+            .map(|v| by_label.get(v.label.as_str()).copied().ok_or_else(|| mismatch(format!("no stored parameter {}", v.label))))
+            .collect::<Result<Vec<f64>, _>>()?;
+        for (value, data) in values.iter_mut().zip(data) {
+            value.set_data(data);
+        }
+        Ok(())
+    }
+}
+
+fn encode<T: Serialize>(payload: &T, format: Format) -> Result<Vec<u8>, Box<dyn Error>> {
+    let versioned = Versioned { version: FORMAT_VERSION, payload };
+    let bytes = match format {
+        Format::Json => serde_json::to_vec_pretty(&versioned)?,
+        Format::Binary => bincode::serialize(&versioned)?,
+    };
+    Ok(bytes)
+}
+
+fn decode<T: DeserializeOwned>(bytes: &[u8], format: Format) -> Result<T, Box<dyn Error>> {
+    // read the version on its own first, so that an old payload layout is reported as such
+    // instead of as a generic parse error
+    let version: u32 = match format {
+        Format::Json => serde_json::from_slice::<serde_json::Value>(bytes)?
+            .get("version")
+            .and_then(|v| v.as_u64())
+            .ok_or("missing format version")? as u32,
+        Format::Binary => bincode::deserialize(bytes)?,
+    };
+    if version != FORMAT_VERSION {
+        return Err(Box::new(VersionMismatch { found: version, expected: FORMAT_VERSION }));
+    }
+    let versioned: Versioned<T> = match format {
+        Format::Json => serde_json::from_slice(bytes)?,
+        Format::Binary => bincode::deserialize(bytes)?,
+    };
+    Ok(versioned.payload)
+}
+
+/// Backward functions are not part of the serialized graph, set them again from each node's op
+fn restore_backward(value: &mut Value) {
+    value.set_backward(value.op.backward_fn());
+    for child in value.children.iter_mut() {
+        restore_backward(child);
+    }
+}
+
+/// Gives every distinct id of the graph a new one from `new_id`, in depth first order,
+/// copies of a leaf keep sharing theirs
+fn renumber_ids(value: &mut Value, ids: &mut HashMap<Uuid, Uuid>, new_id: &mut impl FnMut() -> Uuid) {
+    let id = *ids.entry(value._id).or_insert_with(&mut *new_id);
+    value.set_id(id);
+    for child in value.children.iter_mut() {
+        renumber_ids(child, ids, new_id);
+    }
+}
+
+/// Encodes the graph with ids numbered from 1 in depth first order, so that the output
+/// only depends on the graph and not on what else the process built before or meanwhile
+pub fn graph_to_bytes(root: &Value, format: Format) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut graph = root.clone();
+    let mut next = 0;
+    renumber_ids(&mut graph, &mut HashMap::new(), &mut || {
+        next += 1;
+        Uuid::from_u128(next)
+    });
+    encode(&graph, format)
+}
+
+/// Decodes a graph, its nodes get new ids from the counter so that they don't clash with the
+/// values already built
+pub fn graph_from_bytes(bytes: &[u8], format: Format) -> Result<Value, Box<dyn Error>> {
+    let mut root: Value = decode(bytes, format)?;
+    restore_backward(&mut root);
+    renumber_ids(&mut root, &mut HashMap::new(), &mut next_id);
+    Ok(root)
+}
+
+pub fn params_to_bytes(params: &ParameterSet, format: Format) -> Result<Vec<u8>, Box<dyn Error>> {
+    encode(params, format)
+}
+
+pub fn params_from_bytes(bytes: &[u8], format: Format) -> Result<ParameterSet, Box<dyn Error>> {
+    decode(bytes, format)
+}
+
+/// Saves the whole computational graph (ops, labels, data and grads) rooted at `root`
+pub fn save_graph(root: &Value, file_path: &str, format: Format) -> Result<(), Box<dyn Error>> {
+    fs::write(file_path, graph_to_bytes(root, format)?)?;
+    Ok(())
+}
+
+pub fn load_graph(file_path: &str, format: Format) -> Result<Value, Box<dyn Error>> {
+    graph_from_bytes(&fs::read(file_path)?, format)
+}
+
+pub fn save_params(params: &ParameterSet, file_path: &str, format: Format) -> Result<(), Box<dyn Error>> {
+    fs::write(file_path, params_to_bytes(params, format)?)?;
+    Ok(())
+}
+
+pub fn load_params(file_path: &str, format: Format) -> Result<ParameterSet, Box<dyn Error>> {
+    params_from_bytes(&fs::read(file_path)?, format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nn::{Activation, MLP};
+    use crate::Op;
+    use num_traits::Pow;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use rayon::prelude::*;
+
+    fn example_graph() -> Value {
+        let a = Value::leaf("a", 2.0);
+        let mut c = a.clone() + Value::leaf("b", -3.0);
+        c.set_label("c");
+        let mut e = Value::leaf("d", 1.0).pow(2) * a;
+        e.set_label("e");
+        let mut l = c * e;
+        l.set_label("L");
+        l.backward();
+        l
+    }
+
+    fn ids(value: &Value, out: &mut Vec<Uuid>) {
+        out.push(value._id);
+        for child in value.children.iter() {
+            ids(child, out);
+        }
+    }
+
+    // the ids differ, but two nodes share one in `lhs` exactly when they do in `rhs`
+    fn assert_same_ids(lhs: &Value, rhs: &Value) {
+        let (mut lhs_ids, mut rhs_ids) = (Vec::new(), Vec::new());
+        ids(lhs, &mut lhs_ids);
+        ids(rhs, &mut rhs_ids);
+        assert_eq!(lhs_ids.len(), rhs_ids.len());
+        for i in 0..lhs_ids.len() {
+            for j in 0..lhs_ids.len() {
+                assert_eq!(lhs_ids[i] == lhs_ids[j], rhs_ids[i] == rhs_ids[j]);
+            }
+        }
+    }
+
+    fn assert_same_graph(lhs: &Value, rhs: &Value) {
+        assert_eq!(lhs.label, rhs.label);
+        assert_eq!(lhs.op, rhs.op);
+        assert_eq!(lhs.data.to_bits(), rhs.data.to_bits());
+        assert_eq!(lhs.grad.to_bits(), rhs.grad.to_bits());
+        assert_eq!(lhs.backward.is_some(), rhs.backward.is_some());
+        assert_eq!(lhs.children.len(), rhs.children.len());
+        for (l, r) in lhs.children.iter().zip(rhs.children.iter()) {
+            assert_same_graph(l, r);
+        }
+    }
+
+    #[test]
+    fn graph_round_trip() {
+        let graph = example_graph();
+        for format in [Format::Json, Format::Binary] {
+            let bytes = graph_to_bytes(&graph, format).unwrap();
+            let loaded = graph_from_bytes(&bytes, format).unwrap();
+            assert_same_graph(&graph, &loaded);
+            assert_same_ids(&graph, &loaded);
+            assert_eq!(loaded.op, Op::Mult);
+        }
+    }
+
+    #[test]
+    fn graphs_built_in_parallel_serialize_identically() {
+        let expected = graph_to_bytes(&example_graph(), Format::Json).unwrap();
+        // the threads interleave their ids, the encoded graphs must not show it
+        let graphs: Vec<Value> = (0..16).into_par_iter().map(|_| example_graph()).collect();
+        assert!(graphs.iter().any(|g| g._id != graphs[0]._id));
+        for graph in graphs.iter() {
+            assert_eq!(graph_to_bytes(graph, Format::Json).unwrap(), expected);
+        }
+
+        // loading twice doesn't reuse ids
+        let first = graph_from_bytes(&expected, Format::Json).unwrap();
+        let second = graph_from_bytes(&expected, Format::Json).unwrap();
+        let (mut first_ids, mut second_ids) = (Vec::new(), Vec::new());
+        ids(&first, &mut first_ids);
+        ids(&second, &mut second_ids);
+        assert!(first_ids.iter().all(|id| !second_ids.contains(id)));
+    }
+
+    #[test]
+    fn params_round_trip() {
+        let values = vec![Value::leaf("w0", 0.25), Value::leaf("w1", -1.5), Value::leaf("b", 0.1)];
+        let params = ParameterSet::from_values(&[2, 1], &values);
+        for format in [Format::Json, Format::Binary] {
+            let bytes = params_to_bytes(&params, format).unwrap();
+            assert_eq!(params_from_bytes(&bytes, format).unwrap(), params);
+        }
+
+        // new values, as built by another run, in another order
+        let mut reloaded = [Value::leaf("b", 0.), Value::leaf("w0", 0.), Value::leaf("w1", 0.)];
+        params.apply_to(&mut reloaded.iter_mut().collect::<Vec<_>>()).unwrap();
+        let data: Vec<f64> = reloaded.iter().map(|v| v.data).collect();
+        assert_eq!(data, vec![0.1, 0.25, -1.5]);
+
+        let mut unknown = [Value::leaf("w0", 0.), Value::leaf("w1", 0.), Value::leaf("c", 0.)];
+        let err = params.apply_to(&mut unknown.iter_mut().collect::<Vec<_>>()).unwrap_err();
+        assert!(err.downcast_ref::<ParameterMismatch>().is_some());
+        assert!(unknown.iter().all(|v| v.data == 0.), "nothing is loaded on error");
+        let mut missing = [Value::leaf("w0", 0.), Value::leaf("w1", 0.)];
+        assert!(params.apply_to(&mut missing.iter_mut().collect::<Vec<_>>()).is_err());
+    }
+
+    #[test]
+    fn mlp_reloads_into_a_new_network() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let saved = MLP::new(&[4, 5, 3], Activation::Tanh, &mut rng);
+        let bytes = params_to_bytes(&saved.parameter_set(), Format::Binary).unwrap();
+
+        // rebuilt as in a later run, with other weights and other ids
+        let mut model = MLP::new(&[4, 5, 3], Activation::Tanh, &mut rng);
+        let x = [0.3, -1.0, 2.5, 0.1];
+        assert_ne!(model.predict(&x), saved.predict(&x));
+        model.load_parameter_set(&params_from_bytes(&bytes, Format::Binary).unwrap()).unwrap();
+        for (p, q) in model.parameters().iter().zip(saved.parameters().iter()) {
+            assert_ne!(p.id(), q.id());
+            assert_eq!(p.data(), q.data(), "{}", p.label());
+        }
+        assert_eq!(model.predict(&x), saved.predict(&x));
+
+        let mut other = MLP::new(&[4, 6, 3], Activation::Tanh, &mut rng);
+        let err = other.load_parameter_set(&saved.parameter_set()).unwrap_err();
+        assert!(err.downcast_ref::<ParameterMismatch>().is_some());
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let params = ParameterSet::default();
+        let stale = Versioned { version: FORMAT_VERSION + 1, payload: &params };
+        let json = serde_json::to_vec(&stale).unwrap();
+        let binary = bincode::serialize(&stale).unwrap();
+
+        let err = params_from_bytes(&json, Format::Json).unwrap_err();
+        assert!(err.downcast_ref::<VersionMismatch>().is_some());
+        let err = params_from_bytes(&binary, Format::Binary).unwrap_err();
+        assert!(err.downcast_ref::<VersionMismatch>().is_some());
+    }
+}
@@ -0,0 +1,32 @@
+//! MLP [3, 4, 2] exported by `backprop::export::mlp_to_rust`, do not edit
+#![allow(clippy::excessive_precision, clippy::unreadable_literal)]
+
+pub const INPUTS: usize = 3;
+pub const OUTPUTS: usize = 2;
+
+const W0: [[f64; 3]; 4] = [[-0.75, 0.125, -0.625], [-0.5, 0.375, -0.375], [-0.25, 0.625, -0.125], [0.0, -0.75, 0.125]];
+const B0: [f64; 4] = [0.25, 0.5, 0.75, -0.625];
+
+const W1: [[f64; 4]; 2] = [[0.25, -0.5, 0.375, -0.375], [-0.25, 0.625, -0.125, 0.75]];
+const B1: [f64; 2] = [0.5, 0.0];
+
+fn dense<const I: usize, const O: usize>(
+    w: &[[f64; I]; O],
+    b: &[f64; O],
+    x: &[f64; I],
+    activation: fn(f64) -> f64,
+) -> [f64; O] {
+    std::array::from_fn(|j| activation(b[j] + w[j].iter().zip(x.iter()).map(|(w, x)| w * x).sum::<f64>()))
+}
+
+/// Output logits of the network for a sample
+pub fn predict(x: &[f64; INPUTS]) -> [f64; OUTPUTS] {
+    let h0 = dense(&W0, &B0, x, f64::tanh);
+    dense(&W1, &B1, &h0, |x| x)
+}
+
+/// Index of the largest logit, i.e. the predicted class
+pub fn predict_class(x: &[f64; INPUTS]) -> usize {
+    let logits = predict(x);
+    (0..OUTPUTS).fold(0, |best, i| if logits[i] > logits[best] { i } else { best })
+}
@@ -0,0 +1,445 @@
+use std::fmt;
+
+use super::{Op, Value};
+
+/// Symbolic expression reconstructed from a `Value` graph, leaves are either
+/// named variables or constants (the `scalar_` operands created by the operators)
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Const(f64),
+    Var(String),
+    Neg(Box<Expr>),
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    Div(Box<Expr>, Box<Expr>),
+    // exponents are always constants, as in `Pow<T> for Value`
+    Pow(Box<Expr>, f64),
+    Tanh(Box<Expr>),
+    Sigmoid(Box<Expr>),
+    Relu(Box<Expr>),
+    Exp(Box<Expr>),
+    Ln(Box<Expr>),
+    Abs(Box<Expr>),
+    // Heaviside step, derivative of relu
+    Step(Box<Expr>),
+    // sign function, derivative of abs
+    Sign(Box<Expr>),
+}
+
+fn variable_name(value: &Value) -> String {
+    if value.label.is_empty() {
+        value._id.to_string()
+    } else {
+        value.label.clone()
+    }
+}
+
+impl Expr {
+    /// Expression computed by `root`, with intermediate nodes expanded down to the leaves
+    pub fn from_value(root: &Value) -> Expr {
+        if root.children.is_empty() {
+            return if root.is_constant() {
+                Expr::Const(root.data)
+            } else {
+                Expr::Var(variable_name(root))
+            };
+        }
+        let lhs = Box::new(Expr::from_value(&root.children[0]));
+        match root.op {
+            Op::Add => Expr::Add(lhs, Box::new(Expr::from_value(&root.children[1]))),
+            Op::Sub => Expr::Sub(lhs, Box::new(Expr::from_value(&root.children[1]))),
+            Op::Mult => Expr::Mul(lhs, Box::new(Expr::from_value(&root.children[1]))),
+            Op::Div => Expr::Div(lhs, Box::new(Expr::from_value(&root.children[1]))),
+            Op::Pow => Expr::Pow(lhs, root.children[1].data),
+            Op::Tanh => Expr::Tanh(lhs),
+            Op::Sigmoid => Expr::Sigmoid(lhs),
+            Op::ReLU => Expr::Relu(lhs),
+            Op::Exp => Expr::Exp(lhs),
+            Op::Log => Expr::Ln(lhs),
+            Op::Abs => Expr::Abs(lhs),
+            // max is piecewise, around the current data it is equal to the largest child
+            Op::Max => {
+                let largest = root.children.iter()
+                    .max_by(|a, b| a.data.total_cmp(&b.data))
+                    .expect("max has children");
+                Expr::from_value(largest)
+            }
+            // the checkpointed segment is rebuilt, a loaded checkpoint is only a value
+            Op::Checkpoint => match root.expand_checkpoint() {
+                Some(segment) => Expr::from_value(&segment),
+                None => Expr::Const(root.data),
+            },
+            // an inner node without an op only carries its data
+            Op::NoOp => Expr::Const(root.data),
+        }
+    }
+
+    /// Derivative with respect to the variable `var`, not simplified
+    pub fn derivative(&self, var: &str) -> Expr {
+        match self {
+            Expr::Const(_) => Expr::Const(0.),
+            Expr::Var(name) => Expr::Const(if name == var { 1. } else { 0. }),
+            Expr::Neg(a) => Expr::Neg(Box::new(a.derivative(var))),
+            Expr::Add(a, b) => Expr::Add(Box::new(a.derivative(var)), Box::new(b.derivative(var))),
+            Expr::Sub(a, b) => Expr::Sub(Box::new(a.derivative(var)), Box::new(b.derivative(var))),
+            // (ab)' = a'b + ab'
+            Expr::Mul(a, b) => Expr::Add(
+                Box::new(Expr::Mul(Box::new(a.derivative(var)), b.clone())),
+                Box::new(Expr::Mul(a.clone(), Box::new(b.derivative(var)))),
+            ),
+            // (a/b)' = (a'b - ab') / b^2
+            Expr::Div(a, b) => Expr::Div(
+                Box::new(Expr::Sub(
+                    Box::new(Expr::Mul(Box::new(a.derivative(var)), b.clone())),
+                    Box::new(Expr::Mul(a.clone(), Box::new(b.derivative(var)))),
+                )),
+                Box::new(Expr::Pow(b.clone(), 2.)),
+            ),
+            // (a^n)' = n a^(n-1) a'
+            Expr::Pow(a, n) => Expr::Mul(
+                Box::new(Expr::Mul(Box::new(Expr::Const(*n)), Box::new(Expr::Pow(a.clone(), n - 1.)))),
+                Box::new(a.derivative(var)),
+            ),
+            // tanh(a)' = (1 - tanh(a)^2) a'
+            Expr::Tanh(a) => Expr::Mul(
+                Box::new(Expr::Sub(Box::new(Expr::Const(1.)), Box::new(Expr::Pow(Box::new(self.clone()), 2.)))),
+                Box::new(a.derivative(var)),
+            ),
+            // sigmoid(a)' = sigmoid(a) (1 - sigmoid(a)) a'
+            Expr::Sigmoid(a) => Expr::Mul(
+                Box::new(Expr::Mul(
+                    Box::new(self.clone()),
+                    Box::new(Expr::Sub(Box::new(Expr::Const(1.)), Box::new(self.clone()))),
+                )),
+                Box::new(a.derivative(var)),
+            ),
+            Expr::Relu(a) => Expr::Mul(Box::new(Expr::Step(a.clone())), Box::new(a.derivative(var))),
+            Expr::Exp(a) => Expr::Mul(Box::new(self.clone()), Box::new(a.derivative(var))),
+            Expr::Ln(a) => Expr::Div(Box::new(a.derivative(var)), a.clone()),
+            Expr::Abs(a) => Expr::Mul(Box::new(Expr::Sign(a.clone())), Box::new(a.derivative(var))),
+            // zero almost everywhere
+            Expr::Step(_) | Expr::Sign(_) => Expr::Const(0.),
+        }
+    }
+
+    /// Folds constants and removes neutral elements, e.g. `0 * x`, `x + 0`, `x^1`
+    pub fn simplify(&self) -> Expr {
+        use Expr::*;
+        match self {
+            Const(_) | Var(_) => self.clone(),
+            Neg(a) => match a.simplify() {
+                Const(c) => Const(-c),
+                Neg(inner) => *inner,
+                a => Neg(Box::new(a)),
+            },
+            Add(a, b) => match (a.simplify(), b.simplify()) {
+                (Const(x), Const(y)) => Const(x + y),
+                (Const(0.), e) | (e, Const(0.)) => e,
+                (a, Neg(b)) => Sub(Box::new(a), b).simplify(),
+                (a, b) if a == b => Mul(Box::new(Const(2.)), Box::new(a)).simplify(),
+                (a, b) => Add(Box::new(a), Box::new(b)),
+            },
+            Sub(a, b) => match (a.simplify(), b.simplify()) {
+                (Const(x), Const(y)) => Const(x - y),
+                (e, Const(0.)) => e,
+                (Const(0.), e) => Neg(Box::new(e)).simplify(),
+                (a, b) if a == b => Const(0.),
+                (a, b) => Sub(Box::new(a), Box::new(b)),
+            },
+            Mul(a, b) => match (a.simplify(), b.simplify()) {
+                (Const(x), Const(y)) => Const(x * y),
+                (Const(0.), _) | (_, Const(0.)) => Const(0.),
+                (Const(1.), e) | (e, Const(1.)) => e,
+                (Const(-1.), e) | (e, Const(-1.)) => Neg(Box::new(e)).simplify(),
+                // keep constants in front and merge them, 2 * (3 * x) = 6 * x
+                (Const(x), Mul(c, e)) | (Mul(c, e), Const(x)) if matches!(*c, Const(_)) => {
+                    let Const(y) = *c else { unreachable!() };
+                    Mul(Box::new(Const(x * y)), e)
+                }
+                (e, Const(c)) => Mul(Box::new(Const(c)), Box::new(e)),
+                (a, Mul(c, e)) | (Mul(c, e), a) if matches!(*c, Const(_)) => {
+                    Mul(c, Box::new(Mul(Box::new(a), e).simplify())).simplify()
+                }
+                (Neg(a), b) | (b, Neg(a)) => Neg(Box::new(Mul(a, Box::new(b)))).simplify(),
+                (a, b) if a == b => Pow(Box::new(a), 2.),
+                (a, b) => Mul(Box::new(a), Box::new(b)),
+            },
+            Div(a, b) => match (a.simplify(), b.simplify()) {
+                (Const(x), Const(y)) if y != 0. => Const(x / y),
+                (Const(0.), _) => Const(0.),
+                (e, Const(1.)) => e,
+                (a, b) if a == b => Const(1.),
+                (a, b) => Div(Box::new(a), Box::new(b)),
+            },
+            Pow(a, n) => match (a.simplify(), *n) {
+                (_, 0.) => Const(1.),
+                (e, 1.) => e,
+                (Const(x), n) => Const(x.powf(n)),
+                // only for integer outer exponents, (x^2)^0.5 is |x| and not x
+                (Pow(e, m), n) if n.fract() == 0. => Pow(e, m * n),
+                (e, n) => Pow(Box::new(e), n),
+            },
+            Tanh(a) => match a.simplify() {
+                Const(x) => Const(x.tanh()),
+                a => Tanh(Box::new(a)),
+            },
+            Sigmoid(a) => match a.simplify() {
+                Const(x) => Const(1. / (1. + (-x).exp())),
+                a => Sigmoid(Box::new(a)),
+            },
+            Relu(a) => match a.simplify() {
+                Const(x) => Const(x.max(0.)),
+                a => Relu(Box::new(a)),
+            },
+            Exp(a) => match a.simplify() {
+                Const(x) => Const(x.exp()),
+                Ln(inner) => *inner,
+                a => Exp(Box::new(a)),
+            },
+            Ln(a) => match a.simplify() {
+                Const(x) => Const(x.ln()),
+                Exp(inner) => *inner,
+                a => Ln(Box::new(a)),
+            },
+            Step(a) => match a.simplify() {
+                Const(x) => Const(if x > 0. { 1. } else { 0. }),
+                a => Step(Box::new(a)),
+            },
+            Abs(a) => match a.simplify() {
+                Const(x) => Const(x.abs()),
+                a => Abs(Box::new(a)),
+            },
+            Sign(a) => match a.simplify() {
+                Const(x) => Const(if x == 0. { 0. } else { x.signum() }),
+                a => Sign(Box::new(a)),
+            },
+        }
+    }
+
+    // binding strength used to decide where parentheses are needed
+    fn precedence(&self) -> u8 {
+        match self {
+            Expr::Add(..) | Expr::Sub(..) => 1,
+            Expr::Mul(..) | Expr::Div(..) => 2,
+            Expr::Neg(_) => 3,
+            Expr::Const(c) if *c < 0. => 3,
+            Expr::Pow(..) => 4,
+            Expr::Const(_) | Expr::Var(_) => 5,
+            Expr::Tanh(_) | Expr::Sigmoid(_) | Expr::Relu(_) | Expr::Exp(_) | Expr::Ln(_) | Expr::Step(_) => 5,
+            Expr::Abs(_) | Expr::Sign(_) => 5,
+        }
+    }
+
+    fn fmt_operand(&self, f: &mut fmt::Formatter<'_>, min_precedence: u8) -> fmt::Result {
+        if self.precedence() < min_precedence {
+            write!(f, "({})", self)
+        } else {
+            write!(f, "{}", self)
+        }
+    }
+
+    fn latex_operand(&self, min_precedence: u8) -> String {
+        if self.precedence() < min_precedence {
+            format!("\\left({}\\right)", self.to_latex())
+        } else {
+            self.to_latex()
+        }
+    }
+
+    /// LaTeX form of the expression, to be wrapped in `$$ $$` on the MathJax enabled pages
+    pub fn to_latex(&self) -> String {
+        match self {
+            Expr::Const(c) => format!("{}", c),
+            Expr::Var(name) => latex_variable(name),
+            Expr::Neg(a) => format!("-{}", a.latex_operand(3)),
+            Expr::Add(a, b) => format!("{} + {}", a.latex_operand(1), b.latex_operand(1)),
+            Expr::Sub(a, b) => format!("{} - {}", a.latex_operand(1), b.latex_operand(2)),
+            Expr::Mul(a, b) => {
+                let rhs = b.latex_operand(if matches!(**b, Expr::Mul(..)) { 2 } else { 3 });
+                format!("{} \\cdot {}", a.latex_operand(2), rhs)
+            }
+            Expr::Div(a, b) => format!("\\frac{{{}}}{{{}}}", a.to_latex(), b.to_latex()),
+            Expr::Pow(a, n) => format!("{{{}}}^{{{}}}", a.latex_operand(5), n),
+            Expr::Tanh(a) => format!("\\tanh\\left({}\\right)", a.to_latex()),
+            Expr::Sigmoid(a) => format!("\\sigma\\left({}\\right)", a.to_latex()),
+            Expr::Relu(a) => format!("\\operatorname{{relu}}\\left({}\\right)", a.to_latex()),
+            Expr::Exp(a) => format!("e^{{{}}}", a.to_latex()),
+            Expr::Ln(a) => format!("\\ln\\left({}\\right)", a.to_latex()),
+            Expr::Step(a) => format!("H\\left({}\\right)", a.to_latex()),
+            Expr::Abs(a) => format!("\\left|{}\\right|", a.to_latex()),
+            Expr::Sign(a) => format!("\\operatorname{{sgn}}\\left({}\\right)", a.to_latex()),
+        }
+    }
+}
+
+// `x_1` is rendered as x with subscript 1, longer names are kept upright
+fn latex_variable(name: &str) -> String {
+    match name.split_once('_') {
+        Some((base, sub)) if !base.is_empty() && !sub.is_empty() => format!("{}_{{{}}}", base, sub),
+        _ if name.chars().count() > 1 => format!("\\mathrm{{{}}}", name),
+        _ => name.to_string(),
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Const(c) => write!(f, "{}", c),
+            Expr::Var(name) => write!(f, "{}", name),
+            Expr::Neg(a) => {
+                write!(f, "-")?;
+                a.fmt_operand(f, 3)
+            }
+            Expr::Add(a, b) => {
+                a.fmt_operand(f, 1)?;
+                write!(f, " + ")?;
+                b.fmt_operand(f, 1)
+            }
+            Expr::Sub(a, b) => {
+                a.fmt_operand(f, 1)?;
+                write!(f, " - ")?;
+                b.fmt_operand(f, 2)
+            }
+            Expr::Mul(a, b) => {
+                a.fmt_operand(f, 2)?;
+                write!(f, " * ")?;
+                // products are associative, only a division on the right needs parentheses
+                b.fmt_operand(f, if matches!(**b, Expr::Mul(..)) { 2 } else { 3 })
+            }
+            Expr::Div(a, b) => {
+                a.fmt_operand(f, 2)?;
+                write!(f, " / ")?;
+                b.fmt_operand(f, 3)
+            }
+            Expr::Pow(a, n) => {
+                a.fmt_operand(f, 5)?;
+                if *n < 0. {
+                    write!(f, "^({})", n)
+                } else {
+                    write!(f, "^{}", n)
+                }
+            }
+            Expr::Tanh(a) => write!(f, "tanh({})", a),
+            Expr::Sigmoid(a) => write!(f, "sigmoid({})", a),
+            Expr::Relu(a) => write!(f, "relu({})", a),
+            Expr::Exp(a) => write!(f, "exp({})", a),
+            Expr::Ln(a) => write!(f, "ln({})", a),
+            Expr::Step(a) => write!(f, "step({})", a),
+            Expr::Abs(a) => write!(f, "|{}|", a),
+            Expr::Sign(a) => write!(f, "sign({})", a),
+        }
+    }
+}
+
+fn collect_variables(value: &Value, variables: &mut Vec<String>) {
+    if value.children.is_empty() {
+        let name = variable_name(value);
+        if !value.is_constant() && !variables.contains(&name) {
+            variables.push(name);
+        }
+    }
+    for child in value.children.iter() {
+        collect_variables(child, variables);
+    }
+}
+
+/// Simplified symbolic derivative of `root` with respect to each of its leaves,
+/// in the order the leaves are first met walking the graph
+pub fn symbolic_gradients(root: &Value) -> Vec<(String, Expr)> {
+    let expr = Expr::from_value(root);
+    let mut variables = Vec::new();
+    collect_variables(root, &mut variables);
+    variables
+        .into_iter()
+        .map(|var| {
+            let derivative = expr.derivative(&var).simplify();
+            (var, derivative)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::parser::parse_graph;
+
+    fn eval(expr: &Expr, vars: &HashMap<String, f64>) -> f64 {
+        let f = |e: &Expr| eval(e, vars);
+        match expr {
+            Expr::Const(c) => *c,
+            Expr::Var(name) => vars[name],
+            Expr::Neg(a) => -f(a),
+            Expr::Add(a, b) => f(a) + f(b),
+            Expr::Sub(a, b) => f(a) - f(b),
+            Expr::Mul(a, b) => f(a) * f(b),
+            Expr::Div(a, b) => f(a) / f(b),
+            Expr::Pow(a, n) => f(a).powf(*n),
+            Expr::Tanh(a) => f(a).tanh(),
+            Expr::Sigmoid(a) => 1. / (1. + (-f(a)).exp()),
+            Expr::Relu(a) => f(a).max(0.),
+            Expr::Exp(a) => f(a).exp(),
+            Expr::Ln(a) => f(a).ln(),
+            Expr::Abs(a) => f(a).abs(),
+            Expr::Step(a) => if f(a) > 0. { 1. } else { 0. },
+            Expr::Sign(a) => if f(a) == 0. { 0. } else { f(a).signum() },
+        }
+    }
+
+    #[test]
+    fn symbolic_gradients_agree_with_backward() {
+        let bindings = HashMap::from([("a", -2.0), ("b", 0.5), ("d", 1.5)]);
+        let vars: HashMap<String, f64> = bindings.iter().map(|(k, v)| (k.to_string(), *v)).collect();
+        for source in [
+            "L = (a + b) * d^2",
+            "tanh(a * b) + exp(a) / b - d",
+            "sigmoid(a)^3 - ln(b) * a * a",
+            "relu(b - a) * abs(a) / (d + 1)^-2",
+            // (a^2)^0.5 is |a|, with `a` negative the exponents can't be merged
+            "(a^2)^0.5 * d",
+        ] {
+            let mut graph = parse_graph(source, &bindings).unwrap();
+            let symbolic = symbolic_gradients(&graph.root);
+            graph.root.backward();
+            let numeric = graph.gradients();
+            assert_eq!(symbolic.len(), numeric.len(), "{}", source);
+            for (var, derivative) in symbolic {
+                let value = eval(&derivative, &vars);
+                assert!((value - numeric[&var]).abs() < 1e-9, "{}: d/d{} = {} gives {} but backward {}", source, var, derivative, value, numeric[&var]);
+            }
+        }
+    }
+
+    #[test]
+    fn latex_golden_strings() {
+        let var = |name: &str| Box::new(Expr::Var(name.to_string()));
+        let cases = [
+            (Expr::Div(var("a"), Box::new(Expr::Pow(var("x_1"), 2.))), "\\frac{a}{{x_{1}}^{2}}"),
+            (Expr::Mul(Box::new(Expr::Const(2.)), Box::new(Expr::Add(var("a"), var("b")))), "2 \\cdot \\left(a + b\\right)"),
+            (Expr::Sub(var("a"), Box::new(Expr::Sub(var("b"), var("c")))), "a - \\left(b - c\\right)"),
+            (Expr::Tanh(var("theta")), "\\tanh\\left(\\mathrm{theta}\\right)"),
+            (Expr::Exp(Box::new(Expr::Neg(var("a")))), "e^{-a}"),
+            (Expr::Mul(Box::new(Expr::Step(var("a"))), Box::new(Expr::Abs(var("b")))), "H\\left(a\\right) \\cdot \\left|b\\right|"),
+        ];
+        for (expr, latex) in cases {
+            assert_eq!(expr.to_latex(), latex);
+        }
+        // derivative of (a + b) d^2 with respect to d, simplified
+        let bindings = HashMap::from([("a", 2.0), ("b", -3.0), ("d", 1.0)]);
+        let graph = parse_graph("L = (a + b) * d^2", &bindings).unwrap();
+        let gradients = symbolic_gradients(&graph.root);
+        let (var, derivative) = gradients.last().unwrap();
+        assert_eq!(var, "d");
+        assert_eq!(derivative.to_latex(), "2 \\cdot \\left(a + b\\right) \\cdot d");
+    }
+
+    #[test]
+    fn nested_powers_merge_for_integer_exponents_only() {
+        let x = Box::new(Expr::Var("x".to_string()));
+        let square_root_of_square = Expr::Pow(Box::new(Expr::Pow(x.clone(), 2.)), 0.5);
+        assert_eq!(square_root_of_square.simplify(), square_root_of_square);
+        assert_eq!(Expr::Pow(Box::new(Expr::Pow(x.clone(), 0.5)), 4.).simplify(), Expr::Pow(x, 2.));
+    }
+}
@@ -0,0 +1,14 @@
+// fixtures shared by the gradient checks of the layers
+
+use super::Value;
+
+/// Sum of `values` with a different weight for each, so that every value gets its own
+/// gradient, a plain sum of normalised values would have zero gradient
+pub fn weighted_sum(values: &[Value]) -> Value {
+    Value::sum(values.iter().enumerate().map(|(i, v)| v.clone() * (1. + i as f64 * 0.37).sin()))
+}
+
+/// Point of `n` inputs spread over `[-5/3, 5/3]`, where the checks are run
+pub fn point(n: usize) -> Vec<f64> {
+    (0..n).map(|i| ((i * 7 % 11) as f64 - 5.) / 3.).collect()
+}
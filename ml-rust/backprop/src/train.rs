@@ -0,0 +1,226 @@
+use std::collections::HashSet;
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use rayon::prelude::*;
+
+use super::datasets::Samples;
+use super::nn::{argmax, softmax_cross_entropy, Model};
+use super::optim::{accumulate_gradients, scale_gradients, GradientClip, Gradients, Optimizer};
+use super::regularization::Penalty;
+use super::schedule::LrSchedule;
+use super::Value;
+
+/// How the per-sample graphs of a mini-batch are built and back-propagated
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BatchMode {
+    Sequential,
+    // one graph per sample on the rayon thread pool, gradients summed in whatever order
+    // the threads finish, so the last bits of the result depend on the number of threads
+    Parallel,
+    // same as `Parallel` but gradients are summed in sample order,
+    // giving the same result as `Sequential` for any number of threads
+    ParallelDeterministic,
+}
+
+#[derive(Debug, Clone)]
+pub struct TrainConfig {
+    pub epochs: usize,
+    pub batch_size: usize,
+    pub batch_mode: BatchMode,
+    // learning rate of each epoch, relative to the optimizer's initial one
+    pub schedule: LrSchedule,
+    pub clip: Option<GradientClip>,
+    // weight penalty added to the loss of every batch
+    pub penalty: Option<Penalty>,
+    // seed of the shuffling of the samples at each epoch
+    pub seed: u64,
+    // print the loss every `log_every` epochs, 0 to stay quiet
+    pub log_every: usize,
+}
+
+impl Default for TrainConfig {
+    fn default() -> TrainConfig {
+        TrainConfig {
+            epochs: 100,
+            batch_size: 16,
+            batch_mode: BatchMode::Sequential,
+            schedule: LrSchedule::Constant,
+            clip: None,
+            penalty: None,
+            seed: 42,
+            log_every: 10,
+        }
+    }
+}
+
+/// Mean loss, training accuracy and learning rate of each epoch
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    pub losses: Vec<f64>,
+    pub accuracies: Vec<f64>,
+    pub learning_rates: Vec<f64>,
+}
+
+/// Graph of the cross entropy loss of the model on a single sample
+pub fn sample_loss(model: &impl Model, features: &[f64], target: usize) -> Value {
+    let logits = model.forward_sample(features);
+    softmax_cross_entropy(&logits, target)
+}
+
+/// Loss of a single sample and the gradients of the parameters
+pub fn sample_gradients(model: &impl Model, features: &[f64], target: usize) -> (f64, Gradients) {
+    let mut loss = sample_loss(model, features, target);
+    loss.backward();
+    let mut grads = Gradients::new();
+    accumulate_gradients(&loss, &mut grads);
+    (loss.data(), grads)
+}
+
+/// Data `fit` trains a model of type `M` on, made of samples that each hold one or more
+/// predictions, e.g. the features of a flower or a sequence with a class per time step
+pub trait TrainingSet<M: Model>: Sync {
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of predictions of sample `i`, losses and gradients are averaged over them
+    fn n_predictions(&self, i: usize) -> usize;
+
+    /// Loss of sample `i` summed over its predictions and the gradients of the leaves
+    fn sample_gradients(&self, model: &M, i: usize) -> (f64, Gradients);
+
+    /// Fraction of the predictions that are correct
+    fn accuracy(&self, model: &M) -> f64;
+}
+
+impl<M: Model> TrainingSet<M> for Samples {
+    fn len(&self) -> usize {
+        Samples::len(self)
+    }
+
+    fn n_predictions(&self, _: usize) -> usize {
+        1
+    }
+
+    fn sample_gradients(&self, model: &M, i: usize) -> (f64, Gradients) {
+        sample_gradients(model, &self.features[i], self.targets[i])
+    }
+
+    fn accuracy(&self, model: &M) -> f64 {
+        accuracy(model, self)
+    }
+}
+
+fn merge((loss, mut grads): (f64, Gradients), (other_loss, other_grads): (f64, Gradients)) -> (f64, Gradients) {
+    for (id, grad) in other_grads {
+        *grads.entry(id).or_insert(0.) += grad;
+    }
+    (loss + other_loss, grads)
+}
+
+/// Builds and back-propagates the loss graph of every sample of a batch, returning the summed
+/// loss and the gradients of the parameters averaged over the predictions of the batch
+pub fn batch_gradients<M: Model>(model: &M, data: &impl TrainingSet<M>, batch: &[usize], mode: BatchMode) -> (f64, Gradients) {
+    let per_sample = |&i: &usize| data.sample_gradients(model, i);
+    let empty = || (0., Gradients::new());
+    let (loss_sum, mut grads) = match mode {
+        BatchMode::Sequential => batch.iter().map(per_sample).fold(empty(), merge),
+        BatchMode::Parallel => batch.par_iter().map(per_sample).reduce(empty, merge),
+        BatchMode::ParallelDeterministic => {
+            // collect keeps the order of the samples, the reduction is then done on one thread
+            let results: Vec<(f64, Gradients)> = batch.par_iter().map(per_sample).collect();
+            results.into_iter().fold(empty(), merge)
+        }
+    };
+    // inputs and constants are leaves too, drop them so that clipping only sees parameters
+    let params: HashSet<_> = model.parameters().iter().map(|p| p.id()).collect();
+    grads.retain(|id, _| params.contains(id));
+    let n_predictions: usize = batch.iter().map(|&i| data.n_predictions(i)).sum();
+    scale_gradients(&mut grads, 1. / n_predictions as f64);
+    (loss_sum, grads)
+}
+
+pub fn accuracy(model: &impl Model, samples: &Samples) -> f64 {
+    let correct = samples.features.iter().zip(samples.targets.iter())
+        .filter(|(x, &y)| argmax(&model.predict(x)) == y)
+        .count();
+    correct as f64 / samples.len() as f64
+}
+
+/// Mini-batch training of `model` on `train` with the cross entropy loss,
+/// losses and accuracies of the history are per prediction
+pub fn fit<M: Model>(model: &mut M, train: &impl TrainingSet<M>, optimizer: &mut impl Optimizer, config: &TrainConfig) -> History {
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let mut indices: Vec<usize> = (0..train.len()).collect();
+    let mut history = History::default();
+    let base_lr = optimizer.learning_rate();
+    let n_predictions: usize = (0..train.len()).map(|i| train.n_predictions(i)).sum();
+
+    for epoch in 0..config.epochs {
+        let lr = config.schedule.learning_rate(base_lr, epoch);
+        optimizer.set_learning_rate(lr);
+        history.learning_rates.push(lr);
+
+        indices.shuffle(&mut rng);
+        let mut epoch_loss = 0.;
+        for batch in indices.chunks(config.batch_size) {
+            let (mut loss, mut grads) = batch_gradients(&*model, train, batch, config.batch_mode);
+            if let Some(penalty) = config.penalty {
+                let mut penalty_loss = penalty.loss(&model.parameters());
+                penalty_loss.backward();
+                let mut penalty_grads = Gradients::new();
+                accumulate_gradients(&penalty_loss, &mut penalty_grads);
+                for (id, grad) in grads.iter_mut() {
+                    *grad += penalty_grads.get(id).copied().unwrap_or_default();
+                }
+                // the epoch loss is a mean over the predictions, count the penalty once per prediction
+                let batch_predictions: usize = batch.iter().map(|&i| train.n_predictions(i)).sum();
+                loss += penalty_loss.data() * batch_predictions as f64;
+            }
+            if let Some(clip) = config.clip {
+                clip.apply(&mut grads);
+            }
+            epoch_loss += loss;
+            optimizer.step(&mut model.parameters_mut(), &grads);
+        }
+        history.losses.push(epoch_loss / n_predictions as f64);
+        history.accuracies.push(train.accuracy(model));
+
+        if config.log_every > 0 && (epoch + 1) % config.log_every == 0 {
+            println!("Epoch {:>4} lr {:.5} loss {:.4} accuracy {:.3}",
+                epoch + 1, lr, history.losses[epoch], history.accuracies[epoch]);
+        }
+    }
+    history
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datasets;
+    use crate::nn::{Activation, MLP};
+
+    #[test]
+    fn deterministic_batches_ignore_thread_count() {
+        let samples = datasets::moons(64, 0.1, 1);
+        let model = MLP::new(&[2, 4, 2], Activation::Tanh, &mut StdRng::seed_from_u64(1));
+        let batch: Vec<usize> = (0..samples.len()).collect();
+
+        let (expected_loss, expected) = batch_gradients(&model, &samples, &batch, BatchMode::Sequential);
+        for threads in [1, 2, 4, 7] {
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+            let (loss, grads) = pool.install(|| {
+                batch_gradients(&model, &samples, &batch, BatchMode::ParallelDeterministic)
+            });
+            assert_eq!(loss.to_bits(), expected_loss.to_bits());
+            assert_eq!(grads.len(), expected.len());
+            for param in model.parameters() {
+                assert_eq!(grads[&param.id()].to_bits(), expected[&param.id()].to_bits());
+            }
+        }
+    }
+}
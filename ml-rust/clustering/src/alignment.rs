@@ -0,0 +1,127 @@
+use ndarray::prelude::*;
+
+/// Hungarian algorithm, the assignment of rows to columns with the smallest total cost.
+/// Returns the column of each row, `None` for the rows left over when there are more rows than columns
+pub fn hungarian(cost: &Array2<f64>) -> Vec<Option<usize>> {
+    let (n_rows, n_cols) = cost.dim();
+    // padded to a square matrix, a dummy row or column costs nothing
+    let n = n_rows.max(n_cols);
+    let at = |i: usize, j: usize| if i < n_rows && j < n_cols { cost[[i, j]] } else { 0. };
+
+    // potentials `u` of the rows and `v` of the columns, `row_of[j]` is the row assigned to column j,
+    // index 0 is a sentinel column and rows and columns are counted from 1
+    let mut u = vec![0.; n + 1];
+    let mut v = vec![0.; n + 1];
+    let mut row_of = vec![0; n + 1];
+    let mut previous = vec![0; n + 1];
+    for i in 1..=n {
+        row_of[0] = i;
+        let mut column = 0;
+        let mut min_slack = vec![f64::INFINITY; n + 1];
+        let mut used = vec![false; n + 1];
+        // grows an alternating tree from row i until it reaches a free column
+        loop {
+            used[column] = true;
+            let row = row_of[column];
+            let (mut delta, mut next) = (f64::INFINITY, 0);
+            for j in 1..=n {
+                if !used[j] {
+                    let slack = at(row - 1, j - 1) - u[row] - v[j];
+                    if slack < min_slack[j] {
+                        min_slack[j] = slack;
+                        previous[j] = column;
+                    }
+                    if min_slack[j] < delta {
+                        delta = min_slack[j];
+                        next = j;
+                    }
+                }
+            }
+            for j in 0..=n {
+                if used[j] {
+                    u[row_of[j]] += delta;
+                    v[j] -= delta;
+                } else {
+                    min_slack[j] -= delta;
+                }
+            }
+            column = next;
+            if row_of[column] == 0 {
+                break;
+            }
+        }
+        // flips the path back to the root
+        while column != 0 {
+            let before = previous[column];
+            row_of[column] = row_of[before];
+            column = before;
+        }
+    }
+
+    let mut assignment = vec![None; n_rows];
+    for (j, &row) in row_of.iter().enumerate().skip(1) {
+        if row - 1 < n_rows && j - 1 < n_cols {
+            assignment[row - 1] = Some(j - 1);
+        }
+    }
+    assignment
+}
+
+fn unique(labels: &Array1<usize>) -> Vec<usize> {
+    let mut values = labels.to_vec();
+    values.sort_unstable();
+    values.dedup();
+    values
+}
+
+/// Renames the clusters of `labels` to the labels of `reference` they overlap most with, each
+/// reference label being used once. `reference` can be ground truth classes or the labels of
+/// another run. Clusters left without a match get new ids after the largest reference label
+pub fn align_labels(labels: &Array1<usize>, reference: &Array1<usize>) -> Array1<usize> {
+    assert_eq!(labels.len(), reference.len(), "one label per sample");
+    let (clusters, targets) = (unique(labels), unique(reference));
+    let mut overlap = Array2::zeros((clusters.len(), targets.len()));
+    for (label, target) in labels.iter().zip(reference.iter()) {
+        let (i, j) = (clusters.binary_search(label).unwrap(), targets.binary_search(target).unwrap());
+        overlap[[i, j]] -= 1.;
+    }
+    let assignment = hungarian(&overlap);
+
+    let mut next_id = targets.last().map_or(0, |&t| t + 1);
+    let renamed: Vec<usize> = assignment.iter()
+        .map(|column| match column {
+            Some(j) => targets[*j],
+            None => {
+                next_id += 1;
+                next_id - 1
+            }
+        })
+        .collect();
+    labels.mapv(|label| renamed[clusters.binary_search(&label).unwrap()])
+}
+
+/// Fraction of samples with the same label in both labellings
+pub fn agreement(a: &Array1<usize>, b: &Array1<usize>) -> f64 {
+    a.iter().zip(b.iter()).filter(|(x, y)| x == y).count() as f64 / a.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assignment_and_relabelling() {
+        let cost = array![[4., 1., 3.], [2., 0., 5.], [3., 2., 2.]];
+        assert_eq!(hungarian(&cost), vec![Some(1), Some(0), Some(2)]);
+        // more rows than columns, the most expensive row is left out
+        let tall = array![[1., 9.], [9., 1.], [5., 5.]];
+        assert_eq!(hungarian(&tall), vec![Some(0), Some(1), None]);
+
+        let reference = array![0, 0, 0, 1, 1, 2, 2, 2];
+        let labels = array![2, 2, 1, 0, 0, 1, 1, 3];
+        // 2 -> 0, 0 -> 1 and 1 -> 2 by overlap, 3 is left over and gets a new id
+        let aligned = align_labels(&labels, &reference);
+        assert_eq!(aligned, array![0, 0, 2, 1, 1, 2, 2, 3]);
+        assert!((agreement(&aligned, &reference) - 6. / 8.).abs() < 1e-12);
+    }
+}
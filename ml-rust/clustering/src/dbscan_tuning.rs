@@ -0,0 +1,136 @@
+use std::error::Error;
+use std::fmt;
+
+use linfa::prelude::*;
+use ndarray::prelude::*;
+use plotters::prelude::*;
+
+use crate::dbscan;
+use crate::knee::{kneedle, Curve};
+use crate::metrics::silhouette_score;
+
+fn distance(a: ArrayView1<f64>, b: ArrayView1<f64>) -> f64 {
+    a.iter().zip(b.iter()).map(|(x, y)| (x - y).powi(2)).sum::<f64>().sqrt()
+}
+
+/// Distance of every sample to its k-th nearest neighbour, the sample itself excluded, sorted
+/// in increasing order
+pub fn k_distances(records: &Array2<f64>, k: usize) -> Vec<f64> {
+    assert!(k >= 1 && k < records.nrows(), "k must be in 1..n_samples");
+    let mut result: Vec<f64> = records.outer_iter().enumerate()
+        .map(|(i, x)| {
+            let mut neighbours: Vec<f64> = records.outer_iter().enumerate()
+                .filter(|&(j, _)| j != i)
+                .map(|(_, y)| distance(x, y))
+                .collect();
+            neighbours.sort_unstable_by(f64::total_cmp);
+            neighbours[k - 1]
+        })
+        .collect();
+    result.sort_unstable_by(f64::total_cmp);
+    result
+}
+
+/// Tolerance at the knee of the k-distance curve, with k = `min_points - 1` as linfa counts a
+/// sample among its own neighbours. Samples left of the knee are in dense regions
+pub fn suggest_eps(k_distances: &[f64]) -> Option<f64> {
+    let xs: Vec<f64> = (0..k_distances.len()).map(|i| i as f64).collect();
+    kneedle(&xs, k_distances, Curve::ConvexIncreasing).map(|i| k_distances[i])
+}
+
+/// Sorted k-distance curve, with the suggested tolerance drawn as a horizontal line
+pub fn draw_k_distance(k_distances: &[f64], k: usize, eps: Option<f64>, file_name: &str) -> Result<(), Box<dyn Error>> {
+    let root_area = BitMapBackend::new(file_name, (1000, 800)).into_drawing_area();
+    root_area.fill(&WHITE)?;
+    let top = k_distances.last().copied().unwrap_or(1.) * 1.05;
+    let mut chart = ChartBuilder::on(&root_area)
+        .set_label_area_size(LabelAreaPosition::Left, 60)
+        .set_label_area_size(LabelAreaPosition::Bottom, 60)
+        .margin(20)
+        .caption(format!("{}-distance graph", k), ("sans-serif", 40))
+        .build_cartesian_2d(0..k_distances.len(), 0f64..top)?;
+    chart.configure_mesh()
+        .x_desc("samples sorted by distance")
+        .y_desc(format!("distance to the {}-th nearest neighbour", k))
+        .axis_desc_style(("sans-serif", 20))
+        .draw()?;
+    chart.draw_series(LineSeries::new(k_distances.iter().copied().enumerate(), BLUE.stroke_width(2)))?
+        .label(format!("{}-distance", k))
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], BLUE));
+    if let Some(eps) = eps {
+        chart.draw_series(DashedLineSeries::new(vec![(0, eps), (k_distances.len(), eps)], 10, 5, RED.stroke_width(2)))?
+            .label(format!("suggested eps {:.3}", eps))
+            .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], RED));
+    }
+    chart.configure_series_labels().border_style(BLACK).background_style(WHITE).position(SeriesLabelPosition::UpperLeft).draw()?;
+    root_area.present()?;
+    println!("k-distance graph saved to {}", file_name);
+    Ok(())
+}
+
+/// Outcome of DBSCAN for one pair of parameters
+#[derive(Debug, Clone)]
+pub struct SweepResult {
+    pub min_points: usize,
+    pub eps: f64,
+    pub n_clusters: usize,
+    pub noise_ratio: f64,
+    // over the samples that are not noise, `None` with less than two clusters
+    pub silhouette: Option<f64>,
+}
+
+impl fmt::Display for SweepResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let silhouette = self.silhouette.map_or(String::from("-"), |s| format!("{:.4}", s));
+        write!(
+            f,
+            "{:>10} {:>8.3} {:>10} {:>9.1}% {:>10}",
+            self.min_points, self.eps, self.n_clusters, 100. * self.noise_ratio, silhouette
+        )
+    }
+}
+
+/// Runs DBSCAN for every combination of `min_points` and `eps`
+pub fn sweep(records: &Array2<f64>, min_points: &[usize], eps: &[f64]) -> Vec<SweepResult> {
+    let ds = DatasetBase::from(records.clone());
+    min_points.iter()
+        .flat_map(|&m| eps.iter().map(move |&e| (m, e)))
+        .map(|(min_points, eps)| {
+            let labels = dbscan(&ds, min_points, eps);
+            let mut clusters: Vec<usize> = labels.iter().flatten().copied().collect();
+            clusters.sort_unstable();
+            clusters.dedup();
+            SweepResult {
+                min_points,
+                eps,
+                n_clusters: clusters.len(),
+                noise_ratio: labels.iter().filter(|l| l.is_none()).count() as f64 / labels.len() as f64,
+                silhouette: silhouette_score(records, &labels),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn k_distance_and_eps() {
+        // a dense line of points with a unit spacing and two outliers far away
+        let mut values: Vec<f64> = (0..20).map(f64::from).collect();
+        values.extend([100., 200.]);
+        let records = Array2::from_shape_vec((22, 1), values).unwrap();
+        let distances = k_distances(&records, 2);
+        // the ends of the line have their second neighbour 2 away, the other samples 1
+        assert_eq!(&distances[..18], [1.; 18]);
+        assert_eq!(&distances[18..], [2., 2., 82., 181.]);
+        let eps = suggest_eps(&distances).unwrap();
+        assert_eq!(eps, 2.);
+
+        let results = sweep(&records, &[3], &[eps]);
+        assert_eq!(results[0].n_clusters, 1);
+        assert!((results[0].noise_ratio - 2. / 22.).abs() < 1e-12);
+        assert_eq!(results[0].silhouette, None);
+    }
+}
@@ -0,0 +1,185 @@
+use std::fmt;
+
+use ndarray::prelude::*;
+
+use crate::metrics::ClusterLabel;
+
+/// Number of samples of each class (rows) found in each cluster (columns). DBSCAN noise is
+/// kept as one extra cluster, so noisy samples count against the external scores
+#[derive(Debug, Clone)]
+pub struct Contingency {
+    pub classes: Vec<usize>,
+    // sorted cluster ids, noise last
+    pub clusters: Vec<Option<usize>>,
+    pub counts: Array2<usize>,
+}
+
+impl Contingency {
+    pub fn new<L: ClusterLabel>(truth: &Array1<usize>, labels: &Array1<L>) -> Contingency {
+        assert_eq!(truth.len(), labels.len(), "one label per sample");
+        let mut classes: Vec<usize> = truth.to_vec();
+        classes.sort_unstable();
+        classes.dedup();
+        let mut clusters: Vec<Option<usize>> = labels.iter().map(ClusterLabel::cluster).collect();
+        // `None` sorts first, it is moved to the end
+        clusters.sort_unstable_by_key(|c| (c.is_none(), *c));
+        clusters.dedup();
+
+        let mut counts = Array2::zeros((classes.len(), clusters.len()));
+        for (class, label) in truth.iter().zip(labels.iter()) {
+            let row = classes.binary_search(class).unwrap();
+            let column = clusters.iter().position(|&c| c == label.cluster()).unwrap();
+            counts[[row, column]] += 1;
+        }
+        Contingency { classes, clusters, counts }
+    }
+
+    fn n(&self) -> usize {
+        self.counts.sum()
+    }
+}
+
+impl fmt::Display for Contingency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:<10}", "class")?;
+        for cluster in &self.clusters {
+            match cluster {
+                Some(c) => write!(f, "{:>10}", format!("cluster {}", c))?,
+                None => write!(f, "{:>10}", "noise")?,
+            }
+        }
+        writeln!(f)?;
+        for (class, row) in self.classes.iter().zip(self.counts.outer_iter()) {
+            write!(f, "{:<10}", class)?;
+            for count in row {
+                write!(f, "{:>10}", count)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Agreement between a labelling and the ground truth classes, every score is 1 for a
+/// labelling equal to the classes up to a renaming of the clusters
+#[derive(Debug, Clone)]
+pub struct ExternalScores {
+    // pair counting agreement corrected for chance, 0 on average for random labellings
+    pub adjusted_rand_index: f64,
+    // mutual information over the arithmetic mean of the two entropies
+    pub normalized_mutual_information: f64,
+    // each cluster holds a single class
+    pub homogeneity: f64,
+    // each class sits in a single cluster
+    pub completeness: f64,
+    // harmonic mean of homogeneity and completeness
+    pub v_measure: f64,
+    // geometric mean of pairwise precision and recall
+    pub fowlkes_mallows: f64,
+    // fraction of samples in the majority class of their cluster
+    pub purity: f64,
+}
+
+impl fmt::Display for ExternalScores {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "ARI {:.4}, NMI {:.4}, homogeneity {:.4}, completeness {:.4}, V-measure {:.4}, Fowlkes-Mallows {:.4}, purity {:.4}",
+            self.adjusted_rand_index,
+            self.normalized_mutual_information,
+            self.homogeneity,
+            self.completeness,
+            self.v_measure,
+            self.fowlkes_mallows,
+            self.purity
+        )
+    }
+}
+
+// number of pairs among n samples
+fn pairs(n: usize) -> f64 {
+    (n * n.saturating_sub(1)) as f64 / 2.
+}
+
+fn entropy(sizes: &[usize], n: f64) -> f64 {
+    sizes.iter()
+        .filter(|&&size| size > 0)
+        .map(|&size| {
+            let p = size as f64 / n;
+            -p * p.ln()
+        })
+        .sum()
+}
+
+// a / b where a score of 1 is used for the degenerate 0 / 0 case
+fn ratio_or_one(a: f64, b: f64) -> f64 {
+    if b == 0. { 1. } else { a / b }
+}
+
+impl ExternalScores {
+    pub fn from_contingency(table: &Contingency) -> ExternalScores {
+        let n = table.n() as f64;
+        let class_sizes: Vec<usize> = table.counts.sum_axis(Axis(1)).to_vec();
+        let cluster_sizes: Vec<usize> = table.counts.sum_axis(Axis(0)).to_vec();
+
+        let same_both: f64 = table.counts.iter().map(|&c| pairs(c)).sum();
+        let same_class: f64 = class_sizes.iter().map(|&c| pairs(c)).sum();
+        let same_cluster: f64 = cluster_sizes.iter().map(|&c| pairs(c)).sum();
+        let expected = same_class * same_cluster / pairs(table.n());
+        let max_index = (same_class + same_cluster) / 2.;
+        let adjusted_rand_index = ratio_or_one(same_both - expected, max_index - expected);
+
+        let mutual_information: f64 = table.counts.indexed_iter()
+            .filter(|(_, &count)| count > 0)
+            .map(|((i, j), &count)| {
+                let joint = count as f64 / n;
+                joint * (count as f64 * n / (class_sizes[i] * cluster_sizes[j]) as f64).ln()
+            })
+            .sum();
+        let (class_entropy, cluster_entropy) = (entropy(&class_sizes, n), entropy(&cluster_sizes, n));
+        let homogeneity = ratio_or_one(mutual_information, class_entropy);
+        let completeness = ratio_or_one(mutual_information, cluster_entropy);
+
+        ExternalScores {
+            adjusted_rand_index,
+            normalized_mutual_information: ratio_or_one(mutual_information, (class_entropy + cluster_entropy) / 2.),
+            homogeneity,
+            completeness,
+            v_measure: ratio_or_one(2. * homogeneity * completeness, homogeneity + completeness),
+            fowlkes_mallows: ratio_or_one(same_both, (same_class * same_cluster).sqrt()),
+            purity: table.counts.axis_iter(Axis(1)).map(|column| column.iter().max().copied().unwrap_or(0)).sum::<usize>() as f64 / n,
+        }
+    }
+}
+
+/// Contingency table and external scores of `labels` against the classes `truth`
+pub fn external_scores<L: ClusterLabel>(truth: &Array1<usize>, labels: &Array1<L>) -> (Contingency, ExternalScores) {
+    let table = Contingency::new(truth, labels);
+    let scores = ExternalScores::from_contingency(&table);
+    (table, scores)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scores_against_reference_values() {
+        let truth = array![0, 0, 1, 1];
+        // renamed clusters agree perfectly
+        let (_, perfect) = external_scores(&truth, &array![5, 5, 2, 2]);
+        for score in [perfect.adjusted_rand_index, perfect.normalized_mutual_information, perfect.v_measure, perfect.fowlkes_mallows, perfect.purity] {
+            assert!((score - 1.).abs() < 1e-12);
+        }
+
+        // the second class is split in two, values as given by scikit-learn
+        let (table, split) = external_scores(&truth, &array![Some(0), Some(0), Some(1), None]);
+        assert_eq!(table.counts, array![[2, 0, 0], [0, 1, 1]]);
+        assert!((split.adjusted_rand_index - 4. / 7.).abs() < 1e-12);
+        assert!((split.homogeneity - 1.).abs() < 1e-12);
+        assert!((split.completeness - 2. / 3.).abs() < 1e-12);
+        assert!((split.v_measure - 0.8).abs() < 1e-12);
+        assert!((split.fowlkes_mallows - 0.5f64.sqrt()).abs() < 1e-12);
+        assert!((split.purity - 1.).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,176 @@
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+
+use linfa::prelude::*;
+use linfa_reduction::Pca;
+use ndarray::prelude::*;
+use plotters::prelude::*;
+use rand::prelude::*;
+use rand::rngs::StdRng;
+
+use crate::kmeans_with_rng;
+use crate::metrics::wcss;
+
+/// Box the reference datasets are drawn from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reference {
+    // bounding box of the features
+    BoundingBox,
+    // bounding box along the principal components, follows the data when features are correlated
+    PcaBox,
+}
+
+/// Gap statistic for one k, `gap = E*[log W_k] - log W_k` with `W_k` the WCSS of KMeans
+#[derive(Debug, Clone)]
+pub struct Gap {
+    pub k: usize,
+    pub log_wcss: f64,
+    // mean of log W_k over the reference datasets
+    pub expected_log_wcss: f64,
+    pub gap: f64,
+    // standard deviation of the reference log W_k, scaled by sqrt(1 + 1 / B)
+    pub standard_error: f64,
+}
+
+impl fmt::Display for Gap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "k = {}: log W {:.4}, E*[log W] {:.4}, gap {:.4} ± {:.4}",
+            self.k, self.log_wcss, self.expected_log_wcss, self.gap, self.standard_error
+        )
+    }
+}
+
+fn log_wcss(records: &Array2<f64>, k: usize, rng: &mut StdRng) -> f64 {
+    let ds = DatasetBase::from(records.clone());
+    let (clusters, centroids) = kmeans_with_rng(&ds, k, StdRng::seed_from_u64(rng.gen()));
+    wcss(records, &clusters, &centroids).ln()
+}
+
+// samples uniformly distributed over the bounding box of `records`
+fn uniform_like(records: &Array2<f64>, rng: &mut StdRng) -> Array2<f64> {
+    let min = records.fold_axis(Axis(0), f64::INFINITY, |&m, &v| m.min(v));
+    let max = records.fold_axis(Axis(0), f64::NEG_INFINITY, |&m, &v| m.max(v));
+    Array2::from_shape_fn(records.dim(), |(_, j)| min[j] + rng.gen::<f64>() * (max[j] - min[j]))
+}
+
+/// Tibshirani's gap statistic for every k of `n_clusters`, with `n_references` uniform datasets.
+/// The references and the KMeans initialisations are drawn from `seed`, the same seed gives the same gaps
+pub fn gap_statistic(
+    records: &Array2<f64>,
+    n_clusters: Range<usize>,
+    n_references: usize,
+    reference: Reference,
+    seed: u64,
+) -> Vec<Gap> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    // all the components are kept, the rotation doesn't change the WCSS so the references can be
+    // clustered in PCA space directly
+    let records = match reference {
+        Reference::BoundingBox => records.clone(),
+        Reference::PcaBox => {
+            let ds = DatasetBase::from(records.clone());
+            let pca = Pca::params(records.ncols()).fit(&ds).expect("PCA fitted");
+            pca.predict(records)
+        }
+    };
+    let references: Vec<Array2<f64>> = (0..n_references).map(|_| uniform_like(&records, &mut rng)).collect();
+
+    n_clusters
+        .map(|k| {
+            let log_wcss = log_wcss(&records, k, &mut rng);
+            let reference_logs: Vec<f64> = references.iter().map(|r| self::log_wcss(r, k, &mut rng)).collect();
+            let b = n_references as f64;
+            let expected_log_wcss = reference_logs.iter().sum::<f64>() / b;
+            let variance = reference_logs.iter().map(|l| (l - expected_log_wcss).powi(2)).sum::<f64>() / b;
+            Gap {
+                k,
+                log_wcss,
+                expected_log_wcss,
+                gap: expected_log_wcss - log_wcss,
+                standard_error: variance.sqrt() * (1. + 1. / b).sqrt(),
+            }
+        })
+        .collect()
+}
+
+/// Smallest k with `gap(k) >= gap(k + 1) - s(k + 1)`, `None` when no k of the sweep satisfies it
+pub fn one_standard_error_choice(gaps: &[Gap]) -> Option<usize> {
+    gaps.windows(2)
+        .find(|pair| pair[0].gap >= pair[1].gap - pair[1].standard_error)
+        .map(|pair| pair[0].k)
+}
+
+/// Gap against k with one standard error bars, the 1-SE choice is marked
+pub fn draw_gap(gaps: &[Gap], choice: Option<usize>, file_name: &str) -> Result<(), Box<dyn Error>> {
+    let root_area = BitMapBackend::new(file_name, (1000, 1000)).into_drawing_area();
+    root_area.fill(&WHITE)?;
+
+    let low = gaps.iter().map(|g| g.gap - g.standard_error).fold(f64::INFINITY, f64::min);
+    let high = gaps.iter().map(|g| g.gap + g.standard_error).fold(f64::NEG_INFINITY, f64::max);
+    let pad = (high - low) * 0.1;
+    let k_max = gaps.last().map_or(1, |g| g.k);
+    let mut chart = ChartBuilder::on(&root_area)
+        .set_label_area_size(LabelAreaPosition::Left, 60)
+        .set_label_area_size(LabelAreaPosition::Bottom, 40)
+        .margin(20)
+        .caption("Gap statistic", ("sans-serif", 40))
+        .build_cartesian_2d(0..k_max + 1, (low - pad)..(high + pad))?;
+    chart.configure_mesh().x_desc("k").y_desc("gap").draw()?;
+
+    chart.draw_series(LineSeries::new(gaps.iter().map(|g| (g.k, g.gap)), &BLUE))?
+        .label("gap ± 1 SE")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], BLUE));
+    chart.draw_series(gaps.iter().map(|g| {
+        ErrorBar::new_vertical(g.k, g.gap - g.standard_error, g.gap, g.gap + g.standard_error, BLUE.filled(), 10)
+    }))?;
+    if let Some(gap) = choice.and_then(|k| gaps.iter().find(|g| g.k == k)) {
+        chart.draw_series(std::iter::once(Circle::new((gap.k, gap.gap), 8, RED.filled())))?
+            .label(format!("1-SE choice k = {}", gap.k))
+            .legend(|(x, y)| Circle::new((x + 10, y), 5, RED.filled()));
+    }
+    chart.configure_series_labels().border_style(BLACK).background_style(WHITE).draw()?;
+    root_area.present()?;
+    println!("Gap statistic saved to {}", file_name);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gap(k: usize, gap: f64, standard_error: f64) -> Gap {
+        Gap { k, log_wcss: 0., expected_log_wcss: gap, gap, standard_error }
+    }
+
+    #[test]
+    fn one_standard_error_rule() {
+        // gap(3) is within one standard error of gap(4), 3 is chosen over the maximum
+        let gaps = [gap(1, 0.1, 0.02), gap(2, 0.4, 0.02), gap(3, 0.7, 0.05), gap(4, 0.72, 0.05), gap(5, 0.6, 0.05)];
+        assert_eq!(one_standard_error_choice(&gaps), Some(3));
+        let increasing = [gap(1, 0.1, 0.01), gap(2, 0.2, 0.01)];
+        assert_eq!(one_standard_error_choice(&increasing), None);
+    }
+
+    #[test]
+    fn same_seed_same_gaps() {
+        // four blobs, with points between them so that KMeans depends on its initialisation
+        let records = Array2::from_shape_fn((80, 2), |(i, j)| {
+            let center = [(0., 0.), (3., 0.), (0., 3.), (3., 3.)][i % 4];
+            let offset = ((i * 13 + j * 5) as f64).sin() * 1.2;
+            if j == 0 { center.0 + offset } else { center.1 + offset }
+        });
+        for reference in [Reference::BoundingBox, Reference::PcaBox] {
+            let first = gap_statistic(&records, 1..7, 5, reference, 11);
+            let second = gap_statistic(&records, 1..7, 5, reference, 11);
+            for (a, b) in first.iter().zip(second.iter()) {
+                assert_eq!(a.log_wcss.to_bits(), b.log_wcss.to_bits(), "{} / {}", a, b);
+                assert_eq!(a.gap.to_bits(), b.gap.to_bits(), "{} / {}", a, b);
+                assert_eq!(a.standard_error.to_bits(), b.standard_error.to_bits(), "{} / {}", a, b);
+            }
+            assert_eq!(one_standard_error_choice(&first), one_standard_error_choice(&second));
+        }
+    }
+}
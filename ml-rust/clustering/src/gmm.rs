@@ -0,0 +1,282 @@
+use std::error::Error;
+use std::f64::consts::PI;
+
+use linfa::prelude::*;
+use ndarray::prelude::*;
+use plotters::prelude::*;
+
+use crate::kmeans;
+
+/// Shape of the covariance matrix of each component
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Covariance {
+    // any ellipsoid
+    Full,
+    // ellipsoids aligned with the feature axes, fewer parameters
+    Diagonal,
+}
+
+/// Gaussian mixture fitted with expectation maximisation
+#[derive(Debug, Clone)]
+pub struct GaussianMixture {
+    pub covariance: Covariance,
+    pub weights: Array1<f64>,
+    // one row per component
+    pub means: Array2<f64>,
+    pub covariances: Vec<Array2<f64>>,
+    // of the training data at the last iteration
+    pub log_likelihood: f64,
+    pub n_iterations: usize,
+    pub converged: bool,
+}
+
+// added to the diagonal of the covariances so that a component collapsing on a few samples
+// stays invertible
+const REGULARIZATION: f64 = 1e-6;
+
+// lower triangular L with L L^T = a, `None` when a isn't positive definite
+fn cholesky(a: &Array2<f64>) -> Option<Array2<f64>> {
+    let n = a.nrows();
+    let mut l = Array2::zeros((n, n));
+    for i in 0..n {
+        for j in 0..=i {
+            let sum: f64 = (0..j).map(|k| l[[i, k]] * l[[j, k]]).sum();
+            if i == j {
+                let d = a[[i, i]] - sum;
+                if d <= 0. {
+                    return None;
+                }
+                l[[i, j]] = d.sqrt();
+            } else {
+                l[[i, j]] = (a[[i, j]] - sum) / l[[j, j]];
+            }
+        }
+    }
+    Some(l)
+}
+
+// log density of N(mean, covariance) at every row of `records`
+fn log_gaussian(records: &Array2<f64>, mean: ArrayView1<f64>, covariance: &Array2<f64>) -> Array1<f64> {
+    let d = records.ncols();
+    let l = cholesky(covariance).expect("regularised covariance is positive definite");
+    let log_det: f64 = 2. * l.diag().mapv(f64::ln).sum();
+    records.outer_iter()
+        .map(|x| {
+            // forward substitution of L z = x - mean, |z|^2 is the Mahalanobis distance
+            let mut z = vec![0.; d];
+            for i in 0..d {
+                let sum: f64 = (0..i).map(|k| l[[i, k]] * z[k]).sum();
+                z[i] = (x[i] - mean[i] - sum) / l[[i, i]];
+            }
+            let mahalanobis: f64 = z.iter().map(|v| v * v).sum();
+            -0.5 * (d as f64 * (2. * PI).ln() + log_det + mahalanobis)
+        })
+        .collect()
+}
+
+impl GaussianMixture {
+    /// EM from a KMeans partition, until the mean log likelihood improves by less than `tolerance`
+    pub fn fit(records: &Array2<f64>, n_components: usize, covariance: Covariance, max_iterations: usize, tolerance: f64) -> GaussianMixture {
+        let (n, d) = records.dim();
+        let (labels, _) = kmeans(&DatasetBase::from(records.clone()), n_components);
+        let mut responsibilities = Array2::zeros((n, n_components));
+        for (i, &label) in labels.iter().enumerate() {
+            responsibilities[[i, label]] = 1.;
+        }
+        let mut model = GaussianMixture {
+            covariance,
+            weights: Array1::zeros(n_components),
+            means: Array2::zeros((n_components, d)),
+            covariances: vec![],
+            log_likelihood: f64::NEG_INFINITY,
+            n_iterations: 0,
+            converged: false,
+        };
+        model.maximization(records, &responsibilities);
+        for iteration in 1..=max_iterations {
+            let (next, log_likelihood) = model.expectation(records);
+            responsibilities = next;
+            let improvement = (log_likelihood - model.log_likelihood) / n as f64;
+            model.log_likelihood = log_likelihood;
+            model.n_iterations = iteration;
+            model.maximization(records, &responsibilities);
+            if improvement.abs() < tolerance {
+                model.converged = true;
+                break;
+            }
+        }
+        // the likelihood of the final parameters
+        model.log_likelihood = model.expectation(records).1;
+        model
+    }
+
+    // weights, means and covariances maximising the expected log likelihood
+    fn maximization(&mut self, records: &Array2<f64>, responsibilities: &Array2<f64>) {
+        let d = records.ncols();
+        // floored so that an empty component doesn't divide by zero
+        let totals = responsibilities.sum_axis(Axis(0)).mapv(|t| t.max(1e-10));
+        self.weights = &totals / records.nrows() as f64;
+        self.means = responsibilities.t().dot(records) / totals.clone().insert_axis(Axis(1));
+        self.covariances = (0..totals.len())
+            .map(|c| {
+                let centered = records - &self.means.row(c);
+                let weighted = &centered * &responsibilities.column(c).insert_axis(Axis(1));
+                let mut covariance = weighted.t().dot(&centered) / totals[c];
+                if self.covariance == Covariance::Diagonal {
+                    covariance = Array2::from_diag(&covariance.diag());
+                }
+                covariance + Array2::<f64>::eye(d) * REGULARIZATION
+            })
+            .collect();
+    }
+
+    // responsibilities of the components for each sample, and the log likelihood
+    fn expectation(&self, records: &Array2<f64>) -> (Array2<f64>, f64) {
+        let mut log_probabilities = Array2::zeros((records.nrows(), self.weights.len()));
+        for (c, mut column) in log_probabilities.columns_mut().into_iter().enumerate() {
+            column.assign(&(log_gaussian(records, self.means.row(c), &self.covariances[c]) + self.weights[c].ln()));
+        }
+        let mut log_likelihood = 0.;
+        for mut row in log_probabilities.rows_mut() {
+            // log-sum-exp around the largest term
+            let max = row.fold(f64::NEG_INFINITY, |m, &v| m.max(v));
+            let log_norm = max + row.mapv(|v| (v - max).exp()).sum().ln();
+            row.mapv_inplace(|v| (v - log_norm).exp());
+            log_likelihood += log_norm;
+        }
+        (log_probabilities, log_likelihood)
+    }
+
+    /// Soft assignment, the probability of each component for each sample
+    pub fn predict_proba(&self, records: &Array2<f64>) -> Array2<f64> {
+        self.expectation(records).0
+    }
+
+    /// Hard assignment to the most probable component
+    pub fn predict(&self, records: &Array2<f64>) -> Array1<usize> {
+        self.predict_proba(records).outer_iter()
+            .map(|p| p.iter().enumerate().fold(0, |best, (c, &v)| if v > p[best] { c } else { best }))
+            .collect()
+    }
+
+    /// Number of free parameters: weights summing to 1, means and covariances
+    pub fn n_parameters(&self) -> usize {
+        let (k, d) = self.means.dim();
+        let covariance = match self.covariance {
+            Covariance::Full => d * (d + 1) / 2,
+            Covariance::Diagonal => d,
+        };
+        k - 1 + k * d + k * covariance
+    }
+
+    /// Bayesian information criterion on `n_samples` samples, lower is better
+    pub fn bic(&self, n_samples: usize) -> f64 {
+        -2. * self.log_likelihood + self.n_parameters() as f64 * (n_samples as f64).ln()
+    }
+
+    /// Akaike information criterion, lower is better
+    pub fn aic(&self) -> f64 {
+        -2. * self.log_likelihood + 2. * self.n_parameters() as f64
+    }
+}
+
+// points of the ellipse at `n_std` standard deviations of a 2-D gaussian
+fn ellipse(mean: (f64, f64), covariance: &Array2<f64>, n_std: f64) -> Vec<(f64, f64)> {
+    let (a, b, c) = (covariance[[0, 0]], covariance[[0, 1]], covariance[[1, 1]]);
+    // eigen decomposition of the symmetric 2x2 matrix
+    let half_trace = (a + c) / 2.;
+    let root = (((a - c) / 2.).powi(2) + b * b).sqrt();
+    let (major, minor) = ((half_trace + root).max(0.).sqrt(), (half_trace - root).max(0.).sqrt());
+    let angle = 0.5 * (2. * b).atan2(a - c);
+    (0..=100)
+        .map(|i| {
+            let t = 2. * PI * i as f64 / 100.;
+            let (x, y) = (n_std * major * t.cos(), n_std * minor * t.sin());
+            (mean.0 + x * angle.cos() - y * angle.sin(), mean.1 + x * angle.sin() + y * angle.cos())
+        })
+        .collect()
+}
+
+/// Samples and components in a 2-D projection `x -> projection (x - center)`, e.g. PCA. Samples
+/// are coloured by their most probable component and faded by how sure the assignment is,
+/// each component is drawn with its 1 and 2 standard deviation ellipses
+pub fn draw_gmm(
+    model: &GaussianMixture,
+    records: &Array2<f64>,
+    projection: &Array2<f64>,
+    center: &Array1<f64>,
+    axis_names: (&str, &str),
+    file_name: &str,
+) -> Result<(), Box<dyn Error>> {
+    let points = (records - center).dot(&projection.t());
+    let probabilities = model.predict_proba(records);
+    let means = (&model.means - center).dot(&projection.t());
+    let covariances: Vec<Array2<f64>> = model.covariances.iter().map(|s| projection.dot(s).dot(&projection.t())).collect();
+    let ellipses: Vec<Vec<(f64, f64)>> = (0..model.weights.len())
+        .flat_map(|c| [1., 2.].map(|n_std| ellipse((means[[c, 0]], means[[c, 1]]), &covariances[c], n_std)))
+        .collect();
+
+    let all = || points.outer_iter().map(|p| (p[0], p[1])).chain(ellipses.iter().flatten().copied());
+    let range = |values: Vec<f64>| {
+        let (min, max) = values.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+        let pad = (max - min).max(1e-6) * 0.05;
+        (min - pad)..(max + pad)
+    };
+    let root_area = BitMapBackend::new(file_name, (1000, 1000)).into_drawing_area();
+    root_area.fill(&WHITE)?;
+    let title = format!("Gaussian mixture, {} components, {:?} covariance", model.weights.len(), model.covariance);
+    let mut chart = ChartBuilder::on(&root_area)
+        .set_label_area_size(LabelAreaPosition::Left, 60)
+        .set_label_area_size(LabelAreaPosition::Bottom, 60)
+        .margin(20)
+        .caption(title, ("sans-serif", 30))
+        .build_cartesian_2d(range(all().map(|p| p.0).collect()), range(all().map(|p| p.1).collect()))?;
+    chart.configure_mesh().x_desc(axis_names.0).y_desc(axis_names.1).axis_desc_style(("sans-serif", 20)).draw()?;
+
+    let colors = crate::palette(model.weights.len());
+    for (c, &color) in colors.iter().enumerate() {
+        let members = points.outer_iter().zip(probabilities.outer_iter())
+            .filter(|(_, p)| p.iter().enumerate().all(|(other, &v)| v <= p[c] || other == c));
+        chart.draw_series(members.map(|(x, p)| Circle::new((x[0], x[1]), 5, color.mix(p[c]).filled())))?
+            .label(format!("component {} (weight {:.2})", c, model.weights[c]))
+            .legend(move |(x, y)| Circle::new((x, y), 5, color.filled()));
+        for n_std in 0..2 {
+            chart.draw_series(std::iter::once(PathElement::new(ellipses[2 * c + n_std].clone(), color.stroke_width(2))))?;
+        }
+        chart.draw_series(std::iter::once(Cross::new((means[[c, 0]], means[[c, 1]]), 8, BLACK.stroke_width(3))))?;
+    }
+    chart.configure_series_labels().border_style(BLACK).background_style(WHITE).draw()?;
+    root_area.present()?;
+    println!("Gaussian mixture saved to {}", file_name);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::prelude::*;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn recovers_two_blobs() {
+        let mut rng = StdRng::seed_from_u64(0);
+        // 100 samples around (0, 0) and 100 around (10, 5), the second one stretched along x
+        let records = Array2::from_shape_fn((200, 2), |(i, j)| {
+            let noise: f64 = rng.gen_range(-1.0..1.0);
+            if i < 100 { noise } else { [10., 5.][j] + noise * [3., 1.][j] }
+        });
+        let model = GaussianMixture::fit(&records, 2, Covariance::Full, 100, 1e-6);
+        assert!(model.converged);
+        let first = if model.means[[0, 0]] < 5. { 0 } else { 1 };
+        assert!(model.means.row(first).iter().all(|m| m.abs() < 0.3), "{}", model.means);
+        assert!((model.weights[first] - 0.5).abs() < 1e-6);
+        // uniform noise in [-1, 1] has a variance of 1 / 3
+        assert!((model.covariances[1 - first][[0, 0]] - 3.).abs() < 1.);
+        assert_eq!(model.predict(&records).iter().filter(|&&c| c == first).count(), 100);
+
+        let one = GaussianMixture::fit(&records, 1, Covariance::Diagonal, 100, 1e-6);
+        assert_eq!(one.n_parameters(), 4);
+        assert_eq!(model.n_parameters(), 1 + 4 + 6);
+        assert!(model.bic(200) < one.bic(200));
+    }
+}
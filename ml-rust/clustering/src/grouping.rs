@@ -0,0 +1,82 @@
+use ndarray::prelude::*;
+
+use crate::metrics::ClusterLabel;
+
+/// Points of one cluster, or of the noise, ready to be drawn as one series
+#[derive(Debug, Clone, PartialEq)]
+pub struct Group {
+    // `None` for DBSCAN noise
+    pub cluster: Option<usize>,
+    // legend entry
+    pub label: String,
+    pub points: Vec<(f64, f64)>,
+}
+
+/// Groups the rows of `coordinates`, 2-D points, by the label of each sample. Groups are sorted by
+/// cluster id and points keep the order of the samples, noise comes last in a group of its own
+pub fn group_points<L: ClusterLabel>(labels: &Array1<L>, coordinates: &Array2<f64>) -> Vec<Group> {
+    assert_eq!(labels.len(), coordinates.nrows(), "one label per point");
+    assert!(coordinates.ncols() >= 2, "points need two coordinates");
+
+    let mut groups: Vec<Group> = vec![];
+    for (label, point) in labels.iter().zip(coordinates.outer_iter()) {
+        let cluster = label.cluster();
+        let index = match groups.binary_search_by_key(&key_of(cluster), |g| key_of(g.cluster)) {
+            Ok(index) => index,
+            Err(index) => {
+                let label = match cluster {
+                    Some(c) => format!("Cluster {}", c),
+                    None => String::from("Noisy samples"),
+                };
+                groups.insert(index, Group { cluster, label, points: vec![] });
+                index
+            }
+        };
+        groups[index].points.push((point[0], point[1]));
+    }
+    groups
+}
+
+// `None` sorts before any cluster, the key puts it last
+fn key_of(cluster: Option<usize>) -> (bool, Option<usize>) {
+    (cluster.is_none(), cluster)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn coordinates(n: usize) -> Array2<f64> {
+        Array2::from_shape_fn((n, 2), |(i, j)| (10 * i + j) as f64)
+    }
+
+    #[test]
+    fn every_point_is_kept_in_sample_order() {
+        let groups = group_points(&array![2, 0, 2, 1, 0], &coordinates(5));
+        let clusters: Vec<Option<usize>> = groups.iter().map(|g| g.cluster).collect();
+        assert_eq!(clusters, vec![Some(0), Some(1), Some(2)]);
+        // the first point of each cluster isn't dropped
+        assert_eq!(groups[0].points, vec![(10., 11.), (40., 41.)]);
+        assert_eq!(groups[1].points, vec![(30., 31.)]);
+        assert_eq!(groups[2].points, vec![(0., 1.), (20., 21.)]);
+        assert_eq!(groups.iter().map(|g| g.points.len()).sum::<usize>(), 5);
+    }
+
+    #[test]
+    fn noise_gets_one_group_last() {
+        let labels = array![None, Some(1), None, Some(0), None];
+        let groups = group_points(&labels, &coordinates(5));
+        let legend: Vec<&str> = groups.iter().map(|g| g.label.as_str()).collect();
+        assert_eq!(legend, vec!["Cluster 0", "Cluster 1", "Noisy samples"]);
+        assert_eq!(groups[2].cluster, None);
+        assert_eq!(groups[2].points, vec![(0., 1.), (20., 21.), (40., 41.)]);
+    }
+
+    #[test]
+    fn labels_without_noise_have_no_noise_group() {
+        let groups = group_points(&array![0, 0, 0], &coordinates(3));
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].label, "Cluster 0");
+        assert!(group_points(&Array1::<usize>::zeros(0), &Array2::zeros((0, 2))).is_empty());
+    }
+}
@@ -0,0 +1,250 @@
+use std::error::Error;
+
+use ndarray::prelude::*;
+use plotters::prelude::*;
+
+/// Distance between two clusters, updated with the Lance–Williams formula after each merge
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Linkage {
+    // closest pair of samples
+    Single,
+    // furthest pair of samples
+    Complete,
+    // mean distance over all the pairs
+    Average,
+    // increase of the within cluster sum of squares, as a distance like scipy
+    Ward,
+}
+
+/// One step of the agglomeration. Nodes `0..n` are the samples and node `n + i` is the
+/// cluster created by the i-th merge, like the rows of a scipy linkage matrix
+#[derive(Debug, Clone, PartialEq)]
+pub struct Merge {
+    pub left: usize,
+    pub right: usize,
+    pub distance: f64,
+    // number of samples in the new cluster
+    pub size: usize,
+}
+
+/// Full merge tree of agglomerative clustering, from the samples up to a single cluster
+#[derive(Debug, Clone)]
+pub struct Dendrogram {
+    pub n_samples: usize,
+    pub merges: Vec<Merge>,
+}
+
+fn euclidean(a: ArrayView1<f64>, b: ArrayView1<f64>) -> f64 {
+    a.iter().zip(b.iter()).map(|(x, y)| (x - y).powi(2)).sum::<f64>().sqrt()
+}
+
+/// Agglomerative clustering of the rows of `records`, merging the two closest clusters until one
+/// is left. Runs in O(n^3) on the full distance matrix, which is fine for datasets like Iris
+pub fn agglomerative(records: &Array2<f64>, linkage: Linkage) -> Dendrogram {
+    let n = records.nrows();
+    let mut distances = Array2::from_shape_fn((n, n), |(i, j)| euclidean(records.row(i), records.row(j)));
+    // slot i holds node `nodes[i]` of `sizes[i]` samples while `active[i]`
+    let mut nodes: Vec<usize> = (0..n).collect();
+    let mut sizes = vec![1; n];
+    let mut active = vec![true; n];
+    let mut merges = Vec::with_capacity(n.saturating_sub(1));
+
+    for step in 0..n.saturating_sub(1) {
+        let (mut a, mut b, mut closest) = (0, 0, f64::INFINITY);
+        for i in (0..n).filter(|&i| active[i]) {
+            for j in (i + 1..n).filter(|&j| active[j]) {
+                if distances[[i, j]] < closest {
+                    (a, b, closest) = (i, j, distances[[i, j]]);
+                }
+            }
+        }
+        let (size_a, size_b) = (sizes[a] as f64, sizes[b] as f64);
+        for k in (0..n).filter(|&k| active[k] && k != a && k != b) {
+            let (d_ka, d_kb, size_k) = (distances[[k, a]], distances[[k, b]], sizes[k] as f64);
+            let d = match linkage {
+                Linkage::Single => d_ka.min(d_kb),
+                Linkage::Complete => d_ka.max(d_kb),
+                Linkage::Average => (size_a * d_ka + size_b * d_kb) / (size_a + size_b),
+                Linkage::Ward => (((size_k + size_a) * d_ka.powi(2) + (size_k + size_b) * d_kb.powi(2)
+                    - size_k * closest.powi(2))
+                    / (size_k + size_a + size_b))
+                    .sqrt(),
+            };
+            distances[[k, a]] = d;
+            distances[[a, k]] = d;
+        }
+        merges.push(Merge { left: nodes[a], right: nodes[b], distance: closest, size: sizes[a] + sizes[b] });
+        // the merged cluster takes the slot of `a`
+        nodes[a] = n + step;
+        sizes[a] += sizes[b];
+        active[b] = false;
+    }
+    Dendrogram { n_samples: n, merges }
+}
+
+// root of `node` in a union-find forest, with path halving
+fn find(parent: &mut [usize], mut node: usize) -> usize {
+    while parent[node] != node {
+        parent[node] = parent[parent[node]];
+        node = parent[node];
+    }
+    node
+}
+
+impl Dendrogram {
+    // labels after applying the first `n_merges` merges, numbered by first appearance
+    fn labels_after(&self, n_merges: usize) -> Array1<usize> {
+        let n = self.n_samples;
+        let mut parent: Vec<usize> = (0..n + self.merges.len()).collect();
+        for (i, merge) in self.merges.iter().take(n_merges).enumerate() {
+            parent[merge.left] = n + i;
+            parent[merge.right] = n + i;
+        }
+        let mut roots: Vec<usize> = vec![];
+        (0..n)
+            .map(|sample| {
+                let root = find(&mut parent, sample);
+                roots.iter().position(|&r| r == root).unwrap_or_else(|| {
+                    roots.push(root);
+                    roots.len() - 1
+                })
+            })
+            .collect()
+    }
+
+    /// Cuts the tree into `k` clusters
+    pub fn cut_k(&self, k: usize) -> Array1<usize> {
+        self.labels_after(self.n_samples.saturating_sub(k.max(1)))
+    }
+
+    /// Cuts the tree at height `distance`, merges at or below it are kept
+    pub fn cut_distance(&self, distance: f64) -> Array1<usize> {
+        self.labels_after(self.merges.iter().take_while(|m| m.distance <= distance).count())
+    }
+
+    /// Height halfway between the merges that go from `k + 1` to `k` and from `k` to `k - 1`
+    /// clusters, cutting there gives `k` clusters
+    pub fn height_for(&self, k: usize) -> f64 {
+        let n = self.merges.len();
+        let k = k.clamp(1, n + 1);
+        let below = if k <= n { self.merges[n - k].distance } else { 0. };
+        match k {
+            1 => self.merges.last().map_or(0., |m| m.distance) * 1.05,
+            _ => (below + self.merges[n + 1 - k].distance) / 2.,
+        }
+    }
+
+    // leaves from left to right and the x position of every node, leaves at 0, 1, 2, ...
+    fn layout(&self) -> Vec<f64> {
+        let n = self.n_samples;
+        let mut x = vec![0.; n + self.merges.len()];
+        let mut next_leaf = 0.;
+        // depth first from the root, right child pushed first so the left one is placed first
+        let mut stack = vec![(n + self.merges.len() - 1, false)];
+        while let Some((node, children_done)) = stack.pop() {
+            if node < n {
+                x[node] = next_leaf;
+                next_leaf += 1.;
+            } else if children_done {
+                let merge = &self.merges[node - n];
+                x[node] = (x[merge.left] + x[merge.right]) / 2.;
+            } else {
+                let merge = &self.merges[node - n];
+                stack.push((node, true));
+                stack.push((merge.right, false));
+                stack.push((merge.left, false));
+            }
+        }
+        x
+    }
+
+    // height of a node, 0 for the samples
+    fn height(&self, node: usize) -> f64 {
+        if node < self.n_samples { 0. } else { self.merges[node - self.n_samples].distance }
+    }
+}
+
+/// Dendrogram image, each merge is drawn as a bracket at its height. Clusters below the cut
+/// height get their own colour and the cut is drawn as a dashed line
+pub fn draw_dendrogram(dendrogram: &Dendrogram, cut: Option<f64>, title: &str, file_name: &str) -> Result<(), Box<dyn Error>> {
+    if dendrogram.merges.is_empty() {
+        return Err("a dendrogram needs at least two samples".into());
+    }
+    let root_area = BitMapBackend::new(file_name, (1400, 800)).into_drawing_area();
+    root_area.fill(&WHITE)?;
+    let top = dendrogram.merges.last().map_or(1., |m| m.distance) * 1.05;
+    let n = dendrogram.n_samples;
+    let mut chart = ChartBuilder::on(&root_area)
+        .set_label_area_size(LabelAreaPosition::Left, 60)
+        .set_label_area_size(LabelAreaPosition::Bottom, 40)
+        .margin(20)
+        .caption(title, ("sans-serif", 40))
+        .build_cartesian_2d(-1f64..n as f64, 0f64..top)?;
+    chart.configure_mesh()
+        .disable_x_mesh()
+        .disable_x_axis()
+        .x_desc("samples")
+        .y_desc("merge distance")
+        .draw()?;
+
+    let x = dendrogram.layout();
+    // merges below the cut take the colour of the cluster they end up in
+    let labels = cut.map(|height| dendrogram.cut_distance(height));
+    let colors = crate::palette(labels.as_ref().map_or(0, |l| l.iter().max().map_or(0, |&c| c + 1)));
+    // a sample of each node, to find its cluster
+    let mut sample_of: Vec<usize> = (0..n).collect();
+    for merge in &dendrogram.merges {
+        sample_of.push(sample_of[merge.left]);
+    }
+    for (i, merge) in dendrogram.merges.iter().enumerate() {
+        let color = match (&labels, cut) {
+            (Some(labels), Some(height)) if merge.distance <= height => colors[labels[sample_of[n + i]]],
+            _ => BLACK,
+        };
+        let (left, right) = (merge.left, merge.right);
+        let bracket = vec![
+            (x[left], dendrogram.height(left)),
+            (x[left], merge.distance),
+            (x[right], merge.distance),
+            (x[right], dendrogram.height(right)),
+        ];
+        chart.draw_series(std::iter::once(PathElement::new(bracket, color.stroke_width(2))))?;
+    }
+    if let Some(height) = cut {
+        let n_clusters = labels.as_ref().map_or(0, |l| l.iter().max().map_or(0, |&c| c + 1));
+        chart.draw_series(DashedLineSeries::new(vec![(-1., height), (n as f64, height)], 10, 5, RED.stroke_width(2)))?
+            .label(format!("cut at {:.3}, {} clusters", height, n_clusters))
+            .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], RED.stroke_width(2)));
+        chart.configure_series_labels().border_style(BLACK).background_style(WHITE).draw()?;
+    }
+    root_area.present()?;
+    println!("Dendrogram saved to {}", file_name);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merges_and_cuts() {
+        let records = array![[0.], [1.], [5.], [6.5], [20.]];
+        let single = agglomerative(&records, Linkage::Single);
+        let heights: Vec<f64> = single.merges.iter().map(|m| m.distance).collect();
+        assert_eq!(heights, vec![1., 1.5, 4., 13.5]);
+        assert_eq!(single.merges[2], Merge { left: 5, right: 6, distance: 4., size: 4 });
+        let complete = agglomerative(&records, Linkage::Complete);
+        assert_eq!(complete.merges[2].distance, 6.5);
+        let average = agglomerative(&records, Linkage::Average);
+        assert_eq!(average.merges[2].distance, (5. + 6.5 + 4. + 5.5) / 4.);
+        // Ward: sqrt(2 n_a n_b / (n_a + n_b)) times the distance between the centroids 0.5 and 5.75
+        let ward = agglomerative(&records, Linkage::Ward);
+        assert!((ward.merges[2].distance - 2f64.sqrt() * 5.25).abs() < 1e-12);
+
+        assert_eq!(single.cut_k(2), array![0, 0, 0, 0, 1]);
+        assert_eq!(single.cut_k(3), array![0, 0, 1, 1, 2]);
+        assert_eq!(single.cut_distance(1.2), array![0, 0, 1, 2, 3]);
+        assert_eq!(single.cut_distance(single.height_for(3)), single.cut_k(3));
+        assert_eq!(single.cut_k(1), array![0, 0, 0, 0, 0]);
+    }
+}
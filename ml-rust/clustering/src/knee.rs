@@ -0,0 +1,47 @@
+/// Shape of a curve around its knee
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Curve {
+    // falls fast then flattens, e.g. WCSS against k
+    ConvexDecreasing,
+    // flat then rises fast, e.g. sorted k-nearest neighbour distances
+    ConvexIncreasing,
+}
+
+/// Kneedle: with both axes scaled to [0, 1] the knee is the point furthest below the straight
+/// line joining the first and last points. Returns its index, `None` when no point is below the line
+pub fn kneedle(xs: &[f64], ys: &[f64], curve: Curve) -> Option<usize> {
+    assert_eq!(xs.len(), ys.len(), "one y per x");
+    let normalize = |values: &[f64]| -> Vec<f64> {
+        let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let range = if max > min { max - min } else { 1. };
+        values.iter().map(|v| (v - min) / range).collect()
+    };
+    let (xs, ys) = (normalize(xs), normalize(ys));
+    // a decreasing curve is mirrored so that in both cases the line goes from (0, 0) to (1, 1)
+    let difference = |i: usize| match curve {
+        Curve::ConvexIncreasing => xs[i] - ys[i],
+        Curve::ConvexDecreasing => 1. - xs[i] - ys[i],
+    };
+    (0..xs.len())
+        // rounding leaves tiny positive differences on a straight line
+        .filter(|&i| difference(i) > 1e-9)
+        .max_by(|&a, &b| difference(a).total_cmp(&difference(b)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn knee_of_both_shapes() {
+        let xs: Vec<f64> = (1..=8).map(f64::from).collect();
+        let falling = [100., 40., 20., 16., 13., 11., 10., 9.];
+        assert_eq!(kneedle(&xs, &falling, Curve::ConvexDecreasing), Some(2));
+        let rising = [0.1, 0.12, 0.13, 0.15, 0.17, 0.2, 0.5, 1.5];
+        assert_eq!(kneedle(&xs, &rising, Curve::ConvexIncreasing), Some(5));
+        // a straight line has no knee
+        let line: Vec<f64> = xs.iter().map(|x| 9. - x).collect();
+        assert_eq!(kneedle(&xs, &line, Curve::ConvexDecreasing), None);
+    }
+}
@@ -1,12 +1,28 @@
+mod alignment;
+mod dbscan_tuning;
+mod external;
+mod gap;
+mod gmm;
+mod grouping;
+mod hierarchical;
+mod knee;
+mod metrics;
+
 use std::error::Error;
 use std::collections::HashMap;
 use std::ops::Range;
 use linfa::prelude::*;
 use ndarray::prelude::*;
 use rand::prelude::*;
-use plotters::{prelude::*, style::full_palette::{PURPLE, GREY, ORANGE}};
+use plotters::{prelude::*, style::full_palette::ORANGE};
 use linfa_reduction::Pca;
 use linfa_clustering::{KMeans, Dbscan};
+use gap::Reference;
+use gmm::{Covariance, GaussianMixture};
+use grouping::{group_points, Group};
+use hierarchical::Linkage;
+use knee::{kneedle, Curve};
+use metrics::Scores;
 /// Loads the Iris dataset from a CSV file and returns a linfa Dataset
 pub fn load_iris_dataset() -> Dataset<f64, usize, Ix1>{
 
@@ -19,51 +35,91 @@ pub fn load_iris_dataset() -> Dataset<f64, usize, Ix1>{
     ds
 }
 
-pub fn draw_clusters(clusters_points: &HashMap<usize, Vec<(f64,f64)>>, colors: &Vec<&RGBColor>, file_name: &str, labels: &Vec<String>) -> Result<(), Box<dyn Error>>{
-    
-    println!("Clusters to points {:?}", clusters_points);
+/// `n` distinguishable colours, hues evenly spread around the colour wheel. Past 8 colours
+/// every other one is lighter so that neighbouring hues can still be told apart
+pub fn palette(n: usize) -> Vec<RGBColor> {
+    (0..n)
+        .map(|i| {
+            let lightness = if n > 8 && i % 2 == 1 { 0.65 } else { 0.45 };
+            let (r, g, b) = HSLColor(i as f64 / n.max(1) as f64, 0.8, lightness).rgb();
+            RGBColor(r, g, b)
+        })
+        .collect()
+}
+
+// range of `values` widened by 5% on both sides, or by 1 when all the values are equal
+fn padded_range(values: impl Iterator<Item = f64>) -> Range<f64> {
+    let (min, max) = values.fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| (lo.min(v), hi.max(v)));
+    if !min.is_finite() {
+        return -1.0..1.0;
+    }
+    let pad = if max > min { (max - min) * 0.05 } else { 1. };
+    (min - pad)..(max + pad)
+}
+
+/// Scatter plot of 2-D points grouped by cluster, with axes fitted to the data. `colors` is
+/// indexed by cluster id and noise is drawn in orange, `centroids` are drawn as triangles in
+/// the colour of their cluster
+pub fn draw_clusters(
+    groups: &[Group],
+    colors: &[RGBColor],
+    file_name: &str,
+    centroids: &[(usize, (f64, f64))],
+    axis_names: (&str, &str),
+) -> Result<(), Box<dyn Error>>{
 
     let drawing_area_width = 1000;
     let drawing_area_height = 1000;
-    let root_area = BitMapBackend::new(&file_name, (drawing_area_width, drawing_area_height)).into_drawing_area();
+    let root_area = BitMapBackend::new(file_name, (drawing_area_width, drawing_area_height)).into_drawing_area();
     root_area.fill(&WHITE)?;
 
+    let all_points = || groups.iter().flat_map(|g| &g.points).chain(centroids.iter().map(|(_, p)| p));
+    let x_range = padded_range(all_points().map(|p| p.0));
+    let y_range = padded_range(all_points().map(|p| p.1));
     let mut ctx = ChartBuilder::on(&root_area)
-        .set_label_area_size(LabelAreaPosition::Left, 40)
-        .set_label_area_size(LabelAreaPosition::Bottom, 40)
-        .margin_bottom(50)
-        .margin_left(80)
-        .margin_right(10)
+        .set_label_area_size(LabelAreaPosition::Left, 60)
+        .set_label_area_size(LabelAreaPosition::Bottom, 60)
+        .margin(20)
         .caption("Clusters", ("sans-serif", 40))
-        .build_cartesian_2d(2f64..10f64, 0f64..6f64)
-        .unwrap();
-    
+        .build_cartesian_2d(x_range, y_range)?;
+
     ctx.configure_mesh()
-        // .x_labels(0)
-        // .y_labels(0)
-        // .label_style(("sans-serif", 20))
+        .x_desc(axis_names.0)
+        .y_desc(axis_names.1)
+        .axis_desc_style(("sans-serif", 20))
         .draw()?;
 
-    // assuming 6 clusters with elbow 
-    
-    for (i, (_,points)) in clusters_points.iter().enumerate(){
+    // the colour follows the cluster id so that aligned labellings are drawn alike
+    for group in groups {
+        let color = group.cluster.map_or(ORANGE, |cluster| colors[cluster % colors.len()]);
         ctx.draw_series(
-            points.iter().map(|point| Circle::new(*point, 5, colors[i])),
+            group.points.iter().map(|point| Circle::new(*point, 5, color)),
         )?
-        .label(labels[i].clone())
+        .label(group.label.clone())
         .legend(move |(x, y)| {
-            Circle::new((x, y), 5, colors[i].filled())
+            Circle::new((x, y), 5, color.filled())
         });
     }
-    ctx.configure_series_labels().border_style(&BLACK).draw()?;
-    // root_area.draw_text(&feature_names[0],  &("sans-serif", 20).into_text_style(&root_area), (500, 970))?;
-    // root_area.draw_text(&feature_names[1],  &("sans-serif", 20).into_text_style(&root_area), (10, 500))?;
-    
+    if !centroids.is_empty() {
+        ctx.draw_series(centroids.iter().map(|&(cluster, point)| {
+            let color = colors[cluster % colors.len()];
+            // a black triangle behind outlines the coloured one
+            EmptyElement::at(point)
+                + TriangleMarker::new((0, 0), 15, BLACK.filled())
+                + TriangleMarker::new((0, 0), 10, color.filled())
+        }))?
+        .label("Centroids")
+        .legend(|(x, y)| TriangleMarker::new((x, y), 8, BLACK.filled()));
+    }
+    ctx.configure_series_labels().border_style(BLACK).background_style(WHITE).draw()?;
+    root_area.present()?;
+    println!("Clusters saved to {}", file_name);
 
     Ok(())
 }
 
-pub fn draw_wcss(n_clusters: &Range<usize>, wcss: Vec<f64>) -> Result<(), Box<dyn Error>> {
+/// WCSS against k, with the suggested k marked when a knee was found
+pub fn draw_wcss(n_clusters: &Range<usize>, wcss: Vec<f64>, knee: Option<usize>) -> Result<(), Box<dyn Error>> {
 
     let drawing_area_width = 1000;
     let drawing_area_height = 1000;
@@ -88,15 +144,68 @@ pub fn draw_wcss(n_clusters: &Range<usize>, wcss: Vec<f64>) -> Result<(), Box<dy
     chart.configure_mesh().draw()?;
     let series_data: Vec<(usize, f64)> = n_clusters.clone().zip(wcss.iter()).map(|(n, &w)| (n, w)).collect();
     chart.draw_series(LineSeries::new(series_data, &RED))?
-        .label("WCSS");
+        .label("WCSS")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], RED));
+    if let Some(k) = knee {
+        let point = (k, wcss[k - n_clusters.start]);
+        chart.draw_series(std::iter::once(Circle::new(point, 8, BLUE.filled())))?
+            .label(format!("knee at k = {}", k))
+            .legend(|(x, y)| Circle::new((x + 10, y), 5, BLUE.filled()));
+    }
+    chart.configure_series_labels().border_style(BLACK).background_style(WHITE).draw()?;
+    root_area.present()?;
 
+    Ok(())
+}
 
+// one metric against k, in its own panel
+fn draw_score_panel(
+    area: &DrawingArea<BitMapBackend, plotters::coord::Shift>,
+    title: &str,
+    points: &[(usize, f64)],
+    color: &RGBColor,
+) -> Result<(), Box<dyn Error>> {
+    let (min, max) = points.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &(_, v)| (lo.min(v), hi.max(v)));
+    let pad = ((max - min) * 0.1).max(1e-3);
+    let k_min = points.first().map_or(0, |p| p.0);
+    let k_max = points.last().map_or(1, |p| p.0);
+    let mut chart = ChartBuilder::on(area)
+        .set_label_area_size(LabelAreaPosition::Left, 60)
+        .set_label_area_size(LabelAreaPosition::Bottom, 40)
+        .margin(20)
+        .caption(title, ("sans-serif", 30))
+        .build_cartesian_2d(k_min..k_max, (min - pad)..(max + pad))?;
+    chart.configure_mesh().x_desc("k").draw()?;
+    chart.draw_series(LineSeries::new(points.iter().copied(), color))?;
+    chart.draw_series(points.iter().map(|&point| Circle::new(point, 4, color.filled())))?;
     Ok(())
 }
 
-fn kmeans(ds: &Dataset<f64, usize, Ix1>, n_clusters: usize) -> (Array1<usize>, Array2<f64>) {
-    
-    let rng = thread_rng();        
+/// WCSS and the internal scores of the k sweep, one panel each
+pub fn draw_scores(n_clusters: &Range<usize>, wcss: &[f64], scores: &[Scores]) -> Result<(), Box<dyn Error>> {
+    let file_name = "cluster_scores.jpg";
+    let root_area = BitMapBackend::new(file_name, (1400, 1000)).into_drawing_area();
+    root_area.fill(&WHITE)?;
+    let panels = root_area.split_evenly((2, 2));
+    let series = |metric: fn(&Scores) -> f64| -> Vec<(usize, f64)> {
+        n_clusters.clone().zip(scores.iter().map(metric)).collect()
+    };
+    let wcss: Vec<(usize, f64)> = n_clusters.clone().zip(wcss.iter().copied()).collect();
+    draw_score_panel(&panels[0], "WCSS (elbow)", &wcss, &RED)?;
+    draw_score_panel(&panels[1], "Silhouette (higher is better)", &series(|s| s.silhouette), &BLUE)?;
+    draw_score_panel(&panels[2], "Davies-Bouldin (lower is better)", &series(|s| s.davies_bouldin), &GREEN)?;
+    draw_score_panel(&panels[3], "Calinski-Harabasz (higher is better)", &series(|s| s.calinski_harabasz), &MAGENTA)?;
+    root_area.present()?;
+    println!("Scores saved to {}", file_name);
+    Ok(())
+}
+
+fn kmeans<T>(ds: &DatasetBase<Array2<f64>, T>, n_clusters: usize) -> (Array1<usize>, Array2<f64>) {
+    kmeans_with_rng(ds, n_clusters, thread_rng())
+}
+
+/// `kmeans` with the centroids initialised from `rng`, for results that can be reproduced
+fn kmeans_with_rng<T, R: Rng + Clone>(ds: &DatasetBase<Array2<f64>, T>, n_clusters: usize, rng: R) -> (Array1<usize>, Array2<f64>) {
     // Let's configure and run our K-means algorithm
     // We use the builder pattern to specify the hyperparameters
     // `n_clusters` is the only mandatory parameter.
@@ -108,13 +217,13 @@ fn kmeans(ds: &Dataset<f64, usize, Ix1>, n_clusters: usize) -> (Array1<usize>, A
         .expect("KMeans fitted");
 
 
-    let clusters= model.predict(ds);
+    let clusters= model.predict(&ds.records);
     let centroids = model.centroids().to_owned();
     (clusters, centroids)
     // let _ = draw_clusters(clusters, feature_names);
 }
 
-fn dbscan(ds: &Dataset<f64, usize, Ix1>, min_points: usize, tol: f64) -> Array1<Option<usize>> {
+fn dbscan<T>(ds: &DatasetBase<Array2<f64>, T>, min_points: usize, tol: f64) -> Array1<Option<usize>> {
     
            
 
@@ -136,73 +245,168 @@ fn main() {
     println!("Hello, world!");
 
     let ds = load_iris_dataset();
-    let K: Range<usize> = 2..10usize;
+    let k_range: Range<usize> = 2..10usize;
     // let's reduce to two dimensions
     let mut wcss : Vec<f64> = vec![];
-    let n_points = ds.records.len_of(Axis(0));
-    for k in K.clone() {
+    let mut scores: Vec<Scores> = vec![];
+    for k in k_range.clone() {
         
         let (clusters, centroids) = kmeans(&ds, k);
         //println!("Clusters for k = {}, {:?}", k, clusters);
         //println!("Centroids shape {:?}", centroids.shape());
-        
-        let mut squared_distances = Array1::zeros(n_points);
-        // for each cluster k, we compute the within cluster sum of squares WCSS, which is defined 
-        // as the sum of squared distances between each point and its cluster centroid
-        for i in 0..n_points {
-            let point = ds.records.row(i);
-            let centroid = centroids.row(clusters[i]); // Get the corresponding centroid
-            let distance = point.to_owned() - centroid.to_owned(); // Compute the difference
-            squared_distances[i] = distance.dot(&distance); // Compute squared distance
-        }
-        wcss.push(squared_distances.sum());
+        wcss.push(metrics::wcss(&ds.records, &clusters, &centroids));
+        let k_scores = metrics::scores(&ds.records, &clusters).expect("k >= 2 clusters");
+        println!("k = {}: {}", k, k_scores);
+        scores.push(k_scores);
         // let _ = draw_clusters(clusters, &feature_names);
     }
     println!("WCSS calculated as inertia {:?}", wcss);
-    let _ = draw_wcss(&K, wcss);
+    let _ = draw_scores(&k_range, &wcss, &scores);
+    let ks: Vec<f64> = k_range.clone().map(|k| k as f64).collect();
+    let knee = kneedle(&ks, &wcss, Curve::ConvexDecreasing).map(|i| k_range.start + i);
+    let _ = draw_wcss(&k_range, wcss, knee);
 
-    let n_clusters = 3;
-    let (k_means_clusters, _) = kmeans(&ds, n_clusters);
+    for (reference, file_name) in [(Reference::BoundingBox, "gap_statistic_box.jpg"), (Reference::PcaBox, "gap_statistic_pca.jpg")] {
+        println!("Gap statistic, references drawn from the {:?}", reference);
+        let gaps = gap::gap_statistic(&ds.records, 1..k_range.end, 10, reference, 42);
+        for gap in &gaps {
+            println!("{}", gap);
+        }
+        let gap_choice = gap::one_standard_error_choice(&gaps);
+        println!("1-SE choice: {:?}", gap_choice);
+        let _ = gap::draw_gap(&gaps, gap_choice, file_name);
+    }
+
+    // the previous hand-picked value is kept for curves without a knee
+    let n_clusters = knee.unwrap_or(3);
+    println!("Running KMeans with k = {} ({})", n_clusters, if knee.is_some() { "knee of the WCSS curve" } else { "no knee found, default" });
+    let (raw_clusters, k_means_centroids) = kmeans(&ds, n_clusters);
+    // cluster ids are arbitrary, they are renamed after the species they overlap most with
+    let k_means_clusters = alignment::align_labels(&raw_clusters, &ds.targets);
     println!("KMeans clusters {:?}", k_means_clusters);
+    // a contingency table rather than linfa's confusion matrix, which drops the clusters
+    // that match no species when k > 3
+    println!(
+        "KMeans clusters aligned to the species, accuracy {:.4}\n{}",
+        alignment::agreement(&k_means_clusters, &ds.targets),
+        external::Contingency::new(&ds.targets, &k_means_clusters)
+    );
+
+    // a second run finds the same clusters under other ids
+    let (rerun, _) = kmeans(&ds, n_clusters);
+    println!(
+        "Agreement with a second KMeans run: {:.4} as is, {:.4} after alignment",
+        alignment::agreement(&rerun, &k_means_clusters),
+        alignment::agreement(&alignment::align_labels(&rerun, &k_means_clusters), &k_means_clusters)
+    );
     
     
-    // dbscan
-    let dbscan_clusters = dbscan(&ds, 5, 0.5);
+    // dbscan, eps at the knee of the sorted k-distances, k = min_points - 1 as a sample is its own neighbour
+    let min_points = 5;
+    let k_distances = dbscan_tuning::k_distances(&ds.records, min_points - 1);
+    let suggested_eps = dbscan_tuning::suggest_eps(&k_distances);
+    match suggested_eps {
+        Some(eps) => println!("Suggested eps for min_points {}: {:.3}", min_points, eps),
+        None => println!("No knee in the {}-distance graph, eps falls back to 0.5", min_points - 1),
+    }
+    let _ = dbscan_tuning::draw_k_distance(&k_distances, min_points - 1, suggested_eps, "k_distance.jpg");
+    let eps = suggested_eps.unwrap_or(0.5);
+
+    let grid: Vec<f64> = [0.5, 0.75, 1., 1.25, 1.5, 2.].iter().map(|f| f * eps).collect();
+    println!("{:>10} {:>8} {:>10} {:>10} {:>10}", "min_points", "eps", "clusters", "noise", "silhouette");
+    for result in dbscan_tuning::sweep(&ds.records, &[3, 4, 5, 6, 8, 10], &grid) {
+        println!("{}", result);
+    }
+
+    let dbscan_clusters = dbscan(&ds, min_points, eps);
     println!("Dbscan clusters {:?}", dbscan_clusters);
+    match metrics::scores(&ds.records, &dbscan_clusters) {
+        Some(dbscan_scores) => println!("Dbscan: {}", dbscan_scores),
+        None => println!("Dbscan found less than two clusters, no scores"),
+    }
+
+    // agreement with the Iris species, which the clustering never sees
+    for (name, (table, scores)) in [
+        ("KMeans", external::external_scores(&ds.targets, &k_means_clusters)),
+        ("Dbscan", external::external_scores(&ds.targets, &dbscan_clusters)),
+    ] {
+        println!("{} against the species\n{}{}", name, table, scores);
+    }
+
+    // agglomerative clustering cut at the same k as KMeans, Ward is kept for the scatter plot
+    let mut ward_clusters = Array1::zeros(0);
+    for linkage in [Linkage::Single, Linkage::Complete, Linkage::Average, Linkage::Ward] {
+        let dendrogram = hierarchical::agglomerative(&ds.records, linkage);
+        let clusters = alignment::align_labels(&dendrogram.cut_k(n_clusters), &ds.targets);
+        // the same clusters as a cut by distance, drawn on the dendrogram
+        let height = dendrogram.height_for(n_clusters);
+        let name = format!("{:?}", linkage).to_lowercase();
+        println!("Agglomerative clustering, {} linkage, cut at {:.4}", name, height);
+        match metrics::scores(&ds.records, &clusters) {
+            Some(scores) => println!("{}", scores),
+            None => println!("less than two clusters, no internal scores"),
+        }
+        let (table, scores) = external::external_scores(&ds.targets, &clusters);
+        println!("{}{}", table, scores);
+        let title = format!("Dendrogram, {} linkage", name);
+        let _ = hierarchical::draw_dendrogram(&dendrogram, Some(height), &title, &format!("dendrogram_{}.jpg", name));
+        if linkage == Linkage::Ward {
+            ward_clusters = clusters;
+        }
+    }
 
 
     let embedding = Pca::params(2)
         .fit(&ds).unwrap();
-    let reduced_ds = embedding.predict(ds);
-    println!("PCAd dataset {:?}", reduced_ds.records.shape());
+    // `predict` on a dataset would keep the records and return the projection as targets
+    let reduced = embedding.predict(&ds.records);
+    println!("PCAd dataset {:?}", reduced.shape());
+    // linfa's `explained_variance_ratio` is relative to the kept components only, the share of the
+    // total variance is the squared singular value over the total sum of squares
+    let total_sum_of_squares = (&ds.records - embedding.mean()).mapv(|v| v * v).sum();
+    let variance = embedding.singular_values().mapv(|s| s * s / total_sum_of_squares);
+    let component_names: Vec<String> = (0..2)
+        .map(|i| format!("PC{} ({:.1}% of the variance)", i + 1, 100. * variance[i]))
+        .collect();
+    let axis_names = (component_names[0].as_str(), component_names[1].as_str());
+    // centroids projected like the samples, under their aligned id
+    let projected_centroids = embedding.predict(&k_means_centroids);
+    let centroids: HashMap<usize, (f64, f64)> = raw_clusters.iter().zip(k_means_clusters.iter())
+        .map(|(&raw, &aligned)| (aligned, (projected_centroids[[raw, 0]], projected_centroids[[raw, 1]])))
+        .collect();
+    let centroids: Vec<(usize, (f64, f64))> = centroids.into_iter().collect();
 
-    let mut clusters_points_k_means: HashMap<usize, Vec<(f64,f64)>> = HashMap::new();
-    let mut clusters_points_dbscan: HashMap<usize, Vec<(f64,f64)>> = HashMap::new();
-    let k_means_colors: Vec<&RGBColor> = vec![&BLUE, &GREEN, &RED];
-    let mut dbscan_colors: Vec<&RGBColor> = vec![&BLUE, &GREEN, &RED];
-    
-    // split dataset in two clusters
-    for (i, (feature, _)) in reduced_ds.sample_iter().enumerate() {
-        // assume dataset has been reduced to two dimensions with PCA or other similar method
-        let point = (feature[0], feature[1]);
-        clusters_points_k_means.entry(k_means_clusters[i]).and_modify(|points_in_cluster| points_in_cluster.push(point)).or_insert(vec![]);
-        clusters_points_dbscan.entry(match dbscan_clusters[i]{
-            Some(cluster) => cluster,
-            None => {
-                dbscan_colors.push(&ORANGE);
-                dbscan_clusters.iter().max().unwrap().unwrap() + 1
-            }
-        }).and_modify(|points_in_cluster| points_in_cluster.push(point)).or_insert(vec![]);
-    }
-    let kmeans_labels: Vec<String> = clusters_points_k_means.keys().map(|cluster| format!("Cluster {}", cluster)).collect();
-    let dbscan_labels: Vec<String> = clusters_points_dbscan.keys().map(|cluster| {
-        if dbscan_clusters.iter().max().unwrap().unwrap() + 1 == *cluster {
-            String::from("Noisy samples")
-        }
-        else {
-            format!("Cluster {}", cluster)
+    let k_means_colors = palette(k_means_clusters.iter().max().map_or(0, |&c| c + 1));
+    let dbscan_colors = palette(dbscan_clusters.iter().flatten().max().map_or(0, |&c| c + 1));
+    let k_means_groups = group_points(&k_means_clusters, &reduced);
+    let dbscan_groups = group_points(&dbscan_clusters, &reduced);
+    let _ = draw_clusters(&k_means_groups, &k_means_colors, "clusters_iris_kmeans.jpg", &centroids, axis_names);
+    let _ = draw_clusters(&dbscan_groups, &dbscan_colors, "clusters_iris_dbscan.jpg", &[], axis_names);
+    let ward_colors = palette(ward_clusters.iter().max().map_or(0, |&c| c + 1));
+    let _ = draw_clusters(&group_points(&ward_clusters, &reduced), &ward_colors, "clusters_iris_ward.jpg", &[], axis_names);
+
+    // Gaussian mixtures, the number of components with the lowest BIC is kept for each covariance type
+    let n_samples = ds.records.nrows();
+    for covariance in [Covariance::Full, Covariance::Diagonal] {
+        let models: Vec<GaussianMixture> = (1..=6)
+            .map(|k| GaussianMixture::fit(&ds.records, k, covariance, 200, 1e-6))
+            .collect();
+        for model in &models {
+            println!(
+                "GMM {:?}, {} components: log likelihood {:.2}, BIC {:.2}, AIC {:.2}, {} iterations{}",
+                covariance,
+                model.weights.len(),
+                model.log_likelihood,
+                model.bic(n_samples),
+                model.aic(),
+                model.n_iterations,
+                if model.converged { "" } else { ", not converged" }
+            );
         }
-    }).collect();
-    let _ = draw_clusters(&clusters_points_k_means, &k_means_colors, "clusters_iris_kmeans.jpg", &kmeans_labels);
-    let _ = draw_clusters(&clusters_points_dbscan, &dbscan_colors, "clusters_iris_dbscan.jpg", &dbscan_labels);
+        let best = models.iter().min_by(|a, b| a.bic(n_samples).total_cmp(&b.bic(n_samples))).unwrap();
+        let (table, scores) = external::external_scores(&ds.targets, &best.predict(&ds.records));
+        println!("GMM {:?} with the lowest BIC, {} components\n{}{}", covariance, best.weights.len(), table, scores);
+        let file_name = format!("gmm_{:?}.jpg", covariance).to_lowercase();
+        let _ = gmm::draw_gmm(best, &ds.records, embedding.components(), embedding.mean(), axis_names, &file_name);
+    }
 }
@@ -0,0 +1,226 @@
+use std::fmt;
+
+use ndarray::prelude::*;
+
+/// Cluster assigned to a sample, KMeans labels every sample while DBSCAN leaves noise as `None`
+pub trait ClusterLabel {
+    fn cluster(&self) -> Option<usize>;
+}
+
+impl ClusterLabel for usize {
+    fn cluster(&self) -> Option<usize> {
+        Some(*self)
+    }
+}
+
+impl ClusterLabel for Option<usize> {
+    fn cluster(&self) -> Option<usize> {
+        *self
+    }
+}
+
+/// Internal quality scores of a labelling. Noise samples are left out of every score
+#[derive(Debug, Clone)]
+pub struct Scores {
+    // mean silhouette, in [-1, 1], higher is better
+    pub silhouette: f64,
+    // mean similarity of each cluster with its closest one, lower is better
+    pub davies_bouldin: f64,
+    // ratio of between to within cluster dispersion, higher is better
+    pub calinski_harabasz: f64,
+    pub n_clusters: usize,
+    // fraction of samples labelled as noise
+    pub noise_ratio: f64,
+}
+
+impl fmt::Display for Scores {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} clusters, silhouette {:.4}, Davies-Bouldin {:.4}, Calinski-Harabasz {:.2}, noise {:.1}%",
+            self.n_clusters,
+            self.silhouette,
+            self.davies_bouldin,
+            self.calinski_harabasz,
+            100. * self.noise_ratio
+        )
+    }
+}
+
+fn distance(a: ArrayView1<f64>, b: ArrayView1<f64>) -> f64 {
+    a.iter().zip(b.iter()).map(|(x, y)| (x - y).powi(2)).sum::<f64>().sqrt()
+}
+
+// cluster ids remapped to 0..n_clusters, in order of first appearance, noise stays `None`
+fn dense_labels<L: ClusterLabel>(labels: &Array1<L>) -> (Vec<Option<usize>>, usize) {
+    let mut ids: Vec<usize> = vec![];
+    let dense = labels.iter()
+        .map(|label| {
+            label.cluster().map(|cluster| match ids.iter().position(|&id| id == cluster) {
+                Some(index) => index,
+                None => {
+                    ids.push(cluster);
+                    ids.len() - 1
+                }
+            })
+        })
+        .collect();
+    (dense, ids.len())
+}
+
+// centroid and number of samples of each cluster
+fn centroids(records: &Array2<f64>, labels: &[Option<usize>], n_clusters: usize) -> (Array2<f64>, Vec<usize>) {
+    let mut sums = Array2::zeros((n_clusters, records.ncols()));
+    let mut counts = vec![0; n_clusters];
+    for (point, label) in records.outer_iter().zip(labels.iter()) {
+        if let Some(cluster) = *label {
+            let mut sum = sums.row_mut(cluster);
+            sum += &point;
+            counts[cluster] += 1;
+        }
+    }
+    for (mut sum, &count) in sums.outer_iter_mut().zip(counts.iter()) {
+        sum /= count as f64;
+    }
+    (sums, counts)
+}
+
+/// Within cluster sum of squares, the squared distance of each sample to its centroid summed
+/// over the samples
+pub fn wcss(records: &Array2<f64>, labels: &Array1<usize>, centroids: &Array2<f64>) -> f64 {
+    records.outer_iter().zip(labels.iter())
+        .map(|(point, &cluster)| distance(point, centroids.row(cluster)).powi(2))
+        .sum()
+}
+
+/// Silhouette of each sample, `(b - a) / max(a, b)` with `a` the mean distance to the other
+/// samples of its cluster and `b` the mean distance to the samples of the closest other cluster.
+/// `None` for noise samples, samples alone in their cluster get 0
+pub fn silhouette_samples<L: ClusterLabel>(records: &Array2<f64>, labels: &Array1<L>) -> Vec<Option<f64>> {
+    let (labels, n_clusters) = dense_labels(labels);
+    let counts = labels.iter().flatten().fold(vec![0usize; n_clusters], |mut counts, &c| {
+        counts[c] += 1;
+        counts
+    });
+    labels.iter().enumerate()
+        .map(|(i, label)| {
+            let own = (*label)?;
+            if counts[own] < 2 || n_clusters < 2 {
+                return Some(0.);
+            }
+            // summed distance from sample i to every cluster
+            let mut sums = vec![0.; n_clusters];
+            for (j, other) in labels.iter().enumerate() {
+                if let Some(cluster) = *other {
+                    sums[cluster] += distance(records.row(i), records.row(j));
+                }
+            }
+            let a = sums[own] / (counts[own] - 1) as f64;
+            let b = (0..n_clusters)
+                .filter(|&c| c != own)
+                .map(|c| sums[c] / counts[c] as f64)
+                .fold(f64::INFINITY, f64::min);
+            Some((b - a) / a.max(b))
+        })
+        .collect()
+}
+
+/// Mean silhouette over the samples that are not noise, `None` with less than two clusters
+pub fn silhouette_score<L: ClusterLabel>(records: &Array2<f64>, labels: &Array1<L>) -> Option<f64> {
+    if dense_labels(labels).1 < 2 {
+        return None;
+    }
+    let values: Vec<f64> = silhouette_samples(records, labels).into_iter().flatten().collect();
+    Some(values.iter().sum::<f64>() / values.len() as f64)
+}
+
+/// Mean over the clusters of `max_j (s_i + s_j) / d(c_i, c_j)`, with `s_i` the mean distance of the
+/// samples of cluster i to its centroid `c_i`. `None` with less than two clusters
+pub fn davies_bouldin_score<L: ClusterLabel>(records: &Array2<f64>, labels: &Array1<L>) -> Option<f64> {
+    let (labels, n_clusters) = dense_labels(labels);
+    if n_clusters < 2 {
+        return None;
+    }
+    let (centroids, counts) = centroids(records, &labels, n_clusters);
+    let mut scatter = vec![0.; n_clusters];
+    for (point, label) in records.outer_iter().zip(labels.iter()) {
+        if let Some(cluster) = *label {
+            scatter[cluster] += distance(point, centroids.row(cluster)) / counts[cluster] as f64;
+        }
+    }
+    let total: f64 = (0..n_clusters)
+        .map(|i| {
+            (0..n_clusters)
+                .filter(|&j| j != i)
+                .map(|j| (scatter[i] + scatter[j]) / distance(centroids.row(i), centroids.row(j)))
+                .fold(f64::NEG_INFINITY, f64::max)
+        })
+        .sum();
+    Some(total / n_clusters as f64)
+}
+
+/// `(B / (k - 1)) / (W / (n - k))` with `B` the between cluster and `W` the within cluster sum
+/// of squares. `None` with less than two clusters or as many clusters as samples
+pub fn calinski_harabasz_score<L: ClusterLabel>(records: &Array2<f64>, labels: &Array1<L>) -> Option<f64> {
+    let (labels, n_clusters) = dense_labels(labels);
+    let n = labels.iter().flatten().count();
+    if n_clusters < 2 || n_clusters >= n {
+        return None;
+    }
+    let (centroids, counts) = centroids(records, &labels, n_clusters);
+    // mean of the samples that are not noise
+    let mean = centroids.outer_iter().zip(counts.iter())
+        .fold(Array1::zeros(records.ncols()), |mean, (c, &count)| mean + &c * count as f64)
+        / n as f64;
+    let between: f64 = centroids.outer_iter().zip(counts.iter())
+        .map(|(c, &count)| count as f64 * distance(c, mean.view()).powi(2))
+        .sum();
+    let within: f64 = records.outer_iter().zip(labels.iter())
+        .filter_map(|(point, label)| label.map(|cluster| distance(point, centroids.row(cluster)).powi(2)))
+        .sum();
+    Some(between * (n - n_clusters) as f64 / (within * (n_clusters - 1) as f64))
+}
+
+/// All internal scores of a labelling, `None` when it has less than two clusters
+pub fn scores<L: ClusterLabel>(records: &Array2<f64>, labels: &Array1<L>) -> Option<Scores> {
+    let n_noise = labels.iter().filter(|label| label.cluster().is_none()).count();
+    Some(Scores {
+        silhouette: silhouette_score(records, labels)?,
+        davies_bouldin: davies_bouldin_score(records, labels)?,
+        calinski_harabasz: calinski_harabasz_score(records, labels)?,
+        n_clusters: dense_labels(labels).1,
+        noise_ratio: n_noise as f64 / labels.len() as f64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // two tight pairs far apart, plus a noise sample in the middle
+    fn two_blobs() -> (Array2<f64>, Array1<Option<usize>>) {
+        let records = array![[0., 0.], [0., 1.], [10., 0.], [10., 1.], [5., 0.5]];
+        (records, array![Some(3), Some(3), Some(7), Some(7), None])
+    }
+
+    #[test]
+    fn scores_of_separated_clusters() {
+        let (records, labels) = two_blobs();
+        let silhouettes = silhouette_samples(&records, &labels);
+        // a = 1, b = mean(10, sqrt(101))
+        let b = (10. + 101f64.sqrt()) / 2.;
+        assert!((silhouettes[0].unwrap() - (b - 1.) / b).abs() < 1e-12);
+        assert_eq!(silhouettes[4], None);
+
+        let all = scores(&records, &labels).unwrap();
+        assert_eq!(all.n_clusters, 2);
+        assert!((all.noise_ratio - 0.2).abs() < 1e-12);
+        // s = 0.5 for both clusters, centroids 10 apart
+        assert!((all.davies_bouldin - 0.1).abs() < 1e-12);
+        // B = 4 * 25, W = 4 * 0.25, (B / 1) / (W / 2)
+        assert!((all.calinski_harabasz - 200.).abs() < 1e-9);
+
+        let single: Array1<usize> = array![0, 0, 0];
+        assert!(scores(&records.slice(s![..3, ..]).to_owned(), &single).is_none());
+    }
+}
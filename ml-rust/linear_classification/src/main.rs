@@ -1,3 +1,5 @@
+mod softmax_regression;
+
 use std::error::Error;
 
 use linfa::prelude::*;
@@ -8,6 +10,10 @@ use linfa::metrics::ConfusionMatrix;
 use rand::prelude::*;
 use plotters::prelude::*;
 
+use softmax_regression::SoftmaxRegression;
+
+// strength of the L2 penalty on the weights, shared by both models of the comparison so that they fit the same objective
+const ALPHA: f64 = 1.0;
 
 /// Loads the Iris dataset from a CSV file and returns a linfa Dataset
 pub fn load_iris_dataset(split_ratio: f32) -> (Dataset<f64, usize, Ix1>, Dataset<f64, usize, Ix1>){
@@ -191,6 +197,76 @@ fn draw_corr_matrix(sym_cor_matrix: &Array2<f32>, feature_names: &Vec<String>) -
     Ok(())
 }
 
+// softmax intercepts are only defined up to a common shift, they are compared after removing their mean
+fn centered(intercept: &Array1<f64>) -> Array1<f64> {
+    intercept - intercept.mean().unwrap_or(0.)
+}
+
+// objective minimised by both models: summed cross entropy plus `alpha / 2 * |W|^2`
+fn objective(params: &Array2<f64>, intercept: &Array1<f64>, dataset: &Dataset<f64, usize, Ix1>) -> f64 {
+    let logits = dataset.records.dot(params) + intercept;
+    let cross_entropy: f64 = logits.outer_iter().zip(dataset.targets.iter())
+        .map(|(z, &y)| {
+            let max = z.fold(f64::NEG_INFINITY, |m, &v| m.max(v));
+            max + z.mapv(|v| (v - max).exp()).sum().ln() - z[y]
+        })
+        .sum();
+    cross_entropy + ALPHA / 2. * params.mapv(|w| w * w).sum()
+}
+
+/// Trains the autograd softmax regression on the same split and objective as the linfa model
+/// and prints the coefficients, accuracy and confusion matrix of both side by side
+fn compare_with_autograd(
+    train_set: &Dataset<f64, usize, Ix1>,
+    test_set: &Dataset<f64, usize, Ix1>,
+) {
+    // the baseline model stops after 50 iterations without penalty, far from this optimum,
+    // so linfa is fitted again on the same objective as the autograd model
+    let linfa_model = MultiLogisticRegression::default()
+        .alpha(ALPHA)
+        .max_iterations(500)
+        .fit(train_set)
+        .unwrap();
+    let n_classes = linfa_model.classes().len();
+    let autograd_model = SoftmaxRegression::fit(train_set, n_classes, ALPHA, 3000, 0.3);
+
+    println!("\nCoefficients, linfa vs autograd");
+    println!("{:<16} {:>5} {:>10} {:>10} {:>10}", "feature", "class", "linfa", "autograd", "|diff|");
+    let (linfa_params, autograd_params) = (linfa_model.params(), autograd_model.params());
+    let feature_names = train_set.feature_names();
+    let rows = feature_names.iter().map(String::as_str).chain(["intercept"]);
+    let linfa_intercept = centered(linfa_model.intercept());
+    let autograd_intercept = centered(&autograd_model.intercept());
+    let mut max_diff: f64 = 0.;
+    for (i, name) in rows.enumerate() {
+        for k in 0..n_classes {
+            let (a, b) = if i < feature_names.len() {
+                (linfa_params[[i, k]], autograd_params[[i, k]])
+            } else {
+                (linfa_intercept[k], autograd_intercept[k])
+            };
+            max_diff = max_diff.max((a - b).abs());
+            println!("{:<16} {:>5} {:>10.4} {:>10.4} {:>10.2e}", name, k, a, b, (a - b).abs());
+        }
+    }
+    println!("Largest coefficient difference {:.2e}", max_diff);
+    println!(
+        "Training objective: linfa {:.4}, autograd {:.4}",
+        objective(linfa_params, linfa_model.intercept(), train_set),
+        objective(&autograd_params, &autograd_model.intercept(), train_set),
+    );
+
+    let linfa_pred = linfa_model.predict(test_set);
+    let linfa_cm = linfa_pred.confusion_matrix(test_set).unwrap();
+    let autograd_pred = autograd_model.predict(test_set);
+    let autograd_cm = autograd_pred.confusion_matrix(test_set).unwrap();
+    let agreement = linfa_pred.iter().zip(autograd_pred.iter()).filter(|(a, b)| a == b).count();
+    println!("Test accuracy: linfa {:.3}, autograd {:.3}", linfa_cm.accuracy(), autograd_cm.accuracy());
+    println!("Predictions agree on {}/{} test points", agreement, test_set.nsamples());
+    println!("linfa confusion matrix {:?}", linfa_cm);
+    println!("autograd confusion matrix {:?}", autograd_cm);
+}
+
 fn main(){
     let (train_set, test_set) = load_iris_dataset(0.9);
     let corr_matrix = train_set.pearson_correlation();
@@ -206,5 +282,6 @@ fn main(){
     println!("Ground truth: {:?}", test_set.targets.slice(s![0..n_samples_test])); 
 
     println!("Confusion matrix {:?}", cm);
-    
+
+    compare_with_autograd(&train_set, &test_set);
 }
\ No newline at end of file
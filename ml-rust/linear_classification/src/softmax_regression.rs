@@ -0,0 +1,130 @@
+use backprop::datasets::Samples;
+use backprop::nn::{argmax, Activation, MLP};
+use backprop::optim::{accumulate_gradients, Adam, Gradients, Optimizer};
+use backprop::regularization::Penalty;
+use backprop::schedule::LrSchedule;
+use backprop::train::{batch_gradients, BatchMode};
+use linfa::prelude::*;
+use ndarray::prelude::*;
+
+/// Multinomial logistic regression trained with the backprop crate's autograd, minimising the
+/// same objective as linfa's `MultiLogisticRegression`:
+/// `sum_i cross_entropy(W x_i + b, y_i) + alpha / 2 * |W|^2`, the intercept is not penalised
+pub struct SoftmaxRegression {
+    // a single linear layer, `[n_features, n_classes]`
+    model: MLP,
+}
+
+fn to_samples(dataset: &Dataset<f64, usize, Ix1>, n_classes: usize) -> Samples {
+    Samples {
+        features: dataset.records.outer_iter().map(|row| row.to_vec()).collect(),
+        targets: dataset.targets.to_vec(),
+        feature_names: dataset.feature_names(),
+        n_classes,
+    }
+}
+
+impl SoftmaxRegression {
+    /// Full batch Adam for `epochs` steps, the learning rate decays from `lr` to zero so that
+    /// the parameters settle on the optimum instead of oscillating around it
+    pub fn fit(train: &Dataset<f64, usize, Ix1>, n_classes: usize, alpha: f64, epochs: usize, lr: f64) -> SoftmaxRegression {
+        let samples = to_samples(train, n_classes);
+        let n = samples.len() as f64;
+        let mut model = MLP::zeros(&[samples.n_features(), n_classes], Activation::Linear);
+        let mut optimizer = Adam::new(lr);
+        let schedule = LrSchedule::CosineAnnealing { t_max: epochs, min_lr: 0. };
+        let all: Vec<usize> = (0..samples.len()).collect();
+        // the gradients of `batch_gradients` are averaged over the samples, the penalty is scaled to match
+        let penalty = Penalty::L2(alpha / (2. * n));
+
+        for epoch in 0..epochs {
+            optimizer.set_learning_rate(schedule.learning_rate(lr, epoch));
+            let (_, mut grads) = batch_gradients(&model, &samples, &all, BatchMode::Sequential);
+            let weights: Vec<_> = model.layers()[0].neurons().iter().flat_map(|n| n.weights()).collect();
+            let mut penalty_loss = penalty.loss(&weights);
+            penalty_loss.backward();
+            let mut penalty_grads = Gradients::new();
+            accumulate_gradients(&penalty_loss, &mut penalty_grads);
+            for (id, grad) in penalty_grads {
+                if let Some(g) = grads.get_mut(&id) {
+                    *g += grad;
+                }
+            }
+            optimizer.step(&mut model.parameters_mut(), &grads);
+        }
+        SoftmaxRegression { model }
+    }
+
+    /// Weights as a `(n_features, n_classes)` matrix, like linfa's `params()`
+    pub fn params(&self) -> Array2<f64> {
+        let neurons = self.model.layers()[0].neurons();
+        Array2::from_shape_fn((neurons[0].weights().len(), neurons.len()), |(i, k)| neurons[k].weights()[i].data())
+    }
+
+    pub fn intercept(&self) -> Array1<f64> {
+        self.model.layers()[0].neurons().iter().map(|n| n.bias().data()).collect()
+    }
+
+    /// Class probabilities of every sample as a `(n_samples, n_classes)` matrix, the softmax of the logits
+    pub fn predict_proba(&self, dataset: &Dataset<f64, usize, Ix1>) -> Array2<f64> {
+        let n_classes = self.model.layer_sizes()[1];
+        let mut proba = Array2::zeros((dataset.nsamples(), n_classes));
+        for (row, mut out) in dataset.records.outer_iter().zip(proba.outer_iter_mut()) {
+            let logits = self.model.predict(&row.to_vec());
+            let max = logits.iter().fold(f64::NEG_INFINITY, |m, &z| m.max(z));
+            let exp: Array1<f64> = logits.iter().map(|z| (z - max).exp()).collect();
+            out.assign(&(&exp / exp.sum()));
+        }
+        proba
+    }
+
+    pub fn predict(&self, dataset: &Dataset<f64, usize, Ix1>) -> Array1<usize> {
+        self.predict_proba(dataset).outer_iter()
+            .map(|p| argmax(&p.to_vec()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // three well separated blobs around (0, 0), (4, 0) and (0, 4)
+    fn blobs() -> Dataset<f64, usize, Ix1> {
+        let centers = [(0., 0.), (4., 0.), (0., 4.)];
+        let n = 30;
+        let records = Array2::from_shape_fn((n, 2), |(i, j)| {
+            let (x, y) = centers[i % 3];
+            let jitter = ((i * 7 + j * 3) as f64).sin() * 0.8;
+            if j == 0 { x + jitter } else { y + jitter }
+        });
+        let targets = Array1::from_shape_fn(n, |i| i % 3);
+        Dataset::new(records, targets)
+    }
+
+    #[test]
+    fn fits_separable_classes() {
+        let dataset = blobs();
+        let model = SoftmaxRegression::fit(&dataset, 3, 0.1, 300, 0.1);
+        let accuracy = model.predict(&dataset).confusion_matrix(&dataset).unwrap().accuracy();
+        assert_eq!(accuracy, 1.);
+        assert_eq!(model.params().dim(), (2, 3));
+        assert_eq!(model.intercept().len(), 3);
+    }
+
+    #[test]
+    fn probabilities_sum_to_one() {
+        let dataset = blobs();
+        let model = SoftmaxRegression::fit(&dataset, 3, 1., 100, 0.1);
+        let proba = model.predict_proba(&dataset);
+        assert_eq!(proba.dim(), (30, 3));
+        for row in proba.outer_iter() {
+            assert!(row.iter().all(|&p| (0. ..=1.).contains(&p)));
+            assert!((row.sum() - 1.).abs() < 1e-12, "{}", row);
+        }
+        // the most likely class is the predicted one
+        for (row, &class) in proba.outer_iter().zip(model.predict(&dataset).iter()) {
+            assert!(row.iter().all(|&p| p <= row[class]));
+        }
+    }
+}